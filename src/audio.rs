@@ -1,4 +1,7 @@
+use crate::protocol::NetworkTransportProps;
+use crate::Error;
 use std::os::raw::c_int;
+use tracing::{debug, trace};
 
 mod opus_custom {
     #![allow(non_upper_case_globals)]
@@ -9,16 +12,127 @@ mod opus_custom {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+/// Opus bitrate setting, as in the reference client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioQuality {
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    Mono,
+    /// We send mono but receive the mix in stereo. On the wire, this is the
+    /// same as `Stereo`.
+    MonoInStereoOut,
+    Stereo,
+}
+
+/// Number of samples per channel in each coded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSize {
+    /// Opus64, for lower latency.
+    Samples64,
+    Samples128,
+}
+
+/// The audio stream we ask the server to send us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub quality: AudioQuality,
+    pub channels: ChannelMode,
+    pub frame_size: FrameSize,
+    /// Number of frames carried by each audio datagram, as set with
+    /// [`JamulusClientBuilder::block_size_factor`](crate::JamulusClientBuilder::block_size_factor).
+    pub block_size_factor: u16,
+    /// Whether each frame is followed by a sequence number, for reordering.
+    pub sequence_numbers: bool,
+}
+impl AudioFormat {
+    /// Size of one coded frame in bytes, from the reference client's tables.
+    pub fn coded_bytes(&self) -> usize {
+        let stereo = self.channels != ChannelMode::Mono;
+        match (self.frame_size, stereo, self.quality) {
+            (FrameSize::Samples64, false, AudioQuality::Low) => 12,
+            (FrameSize::Samples64, false, AudioQuality::Normal) => 22,
+            (FrameSize::Samples64, false, AudioQuality::High) => 36,
+            (FrameSize::Samples64, true, AudioQuality::Low) => 24,
+            (FrameSize::Samples64, true, AudioQuality::Normal) => 35,
+            (FrameSize::Samples64, true, AudioQuality::High) => 73,
+            (FrameSize::Samples128, false, AudioQuality::Low) => 25,
+            (FrameSize::Samples128, false, AudioQuality::Normal) => 45,
+            (FrameSize::Samples128, false, AudioQuality::High) => 82,
+            (FrameSize::Samples128, true, AudioQuality::Low) => 47,
+            (FrameSize::Samples128, true, AudioQuality::Normal) => 71,
+            (FrameSize::Samples128, true, AudioQuality::High) => 165,
+        }
+    }
+    /// The `NETW_TRANSPORT_PROPS` describing this format.
+    pub fn transport_props(&self) -> NetworkTransportProps {
+        NetworkTransportProps {
+            base_packet_size: self.coded_bytes() as u32 + self.sequence_numbers as u32,
+            block_size_factor: supported_block_size_factor(self.block_size_factor),
+            num_channels: if self.channels == ChannelMode::Mono {
+                1
+            } else {
+                2
+            },
+            sample_rate: 48000,
+            codec: match self.frame_size {
+                FrameSize::Samples64 => NetworkTransportProps::CODEC_OPUS64,
+                FrameSize::Samples128 => NetworkTransportProps::CODEC_OPUS,
+            },
+            flags: if self.sequence_numbers {
+                NetworkTransportProps::FLAG_SEQUENCE_NUMBER
+            } else {
+                0
+            },
+            codec_options: 0,
+        }
+    }
+}
+/// Rounds as described on `JamulusClientBuilder::block_size_factor`. With 0,
+/// we would send empty datagrams and the server would never give us a channel.
+pub(crate) fn supported_block_size_factor(block_size_factor: u16) -> u16 {
+    match block_size_factor {
+        0 | 1 => 1,
+        2 | 3 => 2,
+        _ => 4,
+    }
+}
+
+impl Default for AudioFormat {
+    /// High quality stereo in 128-sample frames, two frames per datagram,
+    /// with sequence numbers.
+    fn default() -> Self {
+        AudioFormat {
+            quality: AudioQuality::High,
+            channels: ChannelMode::Stereo,
+            frame_size: FrameSize::Samples128,
+            block_size_factor: 2,
+            sequence_numbers: true,
+        }
+    }
+}
+
 pub struct Decoder {
     decoder: *mut opus_custom::OpusCustomDecoder,
     mode: *mut opus_custom::OpusCustomMode,
+    channels: usize,
 }
 unsafe impl Send for Decoder {}
 impl Decoder {
-    pub fn new() -> Decoder {
+    pub fn new() -> Result<Decoder, Error> {
         Self::new_with_custom_params(48000, 2, 128)
     }
-    pub fn new_with_custom_params(sample_rate: u32, channels: u8, frame_size: u32) -> Decoder {
+    /// Fails with the Opus error code if the parameters are not supported.
+    pub fn new_with_custom_params(
+        sample_rate: u32,
+        channels: u8,
+        frame_size: u32,
+    ) -> Result<Decoder, Error> {
+        debug!(sample_rate, channels, frame_size, "Creating Opus decoder");
         unsafe {
             let mut err: c_int = 0;
             let mode = opus_custom::opus_custom_mode_create(
@@ -27,26 +141,49 @@ impl Decoder {
                 &mut err,
             );
             if mode.is_null() {
-                panic!("opus_custom_mode_create failed: {}", err);
+                return Err(Error::Opus(err));
             }
             let decoder =
                 opus_custom::opus_custom_decoder_create(mode, channels as c_int, &mut err);
             if decoder.is_null() {
-                panic!("opus_custom_decoder_create failed: {}", err);
+                opus_custom::opus_custom_mode_destroy(mode);
+                return Err(Error::Opus(err));
             }
-            Decoder { decoder, mode }
+            Ok(Decoder {
+                decoder,
+                mode,
+                channels: channels as usize,
+            })
         }
     }
-    pub fn decode(&self, packet: &[u8], buffer: &mut [i16]) -> usize {
-        unsafe {
+    /// Creates a decoder for the audio stream described by `NETW_TRANSPORT_PROPS`.
+    pub fn from_transport_props(props: &NetworkTransportProps) -> Result<Decoder, Error> {
+        Self::new_with_custom_params(
+            props.sample_rate,
+            props.num_channels,
+            props.frame_size() as u32,
+        )
+    }
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+    /// Decodes a frame into interleaved samples, returning the number of
+    /// samples per channel.
+    pub fn decode(&self, packet: &[u8], buffer: &mut [i16]) -> Result<usize, Error> {
+        let decoded = unsafe {
             opus_custom::opus_custom_decode(
                 self.decoder,
                 packet.as_ptr(),
                 packet.len() as c_int,
                 buffer.as_mut_ptr(),
-                (buffer.len() / 2) as c_int,
-            ) as usize
+                (buffer.len() / self.channels) as c_int,
+            )
+        };
+        trace!(len = packet.len(), decoded, "Decoded frame");
+        if decoded < 0 {
+            return Err(Error::Opus(decoded));
         }
+        Ok(decoded as usize)
     }
 }
 
@@ -64,16 +201,57 @@ mod tests {
     use super::*;
     #[test]
     fn test_opus_custom_bindings() {
-        let decoder = Decoder::new();
+        let decoder = Decoder::new().unwrap();
         let mut buffer: [i16; 960] = [0; 960];
         let mut packet = [0u8; 165];
         packet[0] = 0x04;
         packet[1] = 0xff;
         packet[2] = 0xfe;
-        let decoded = decoder.decode(&packet, &mut buffer);
+        let decoded = decoder.decode(&packet, &mut buffer).unwrap();
         assert_eq!(decoded, 128);
         for sample in buffer {
             assert_eq!(sample, 0);
         }
     }
+
+    #[test]
+    fn audio_format_describes_transport_props() {
+        let props = AudioFormat::default().transport_props();
+        assert_eq!(props.base_packet_size, 166);
+        assert_eq!(props.block_size_factor, 2);
+        assert_eq!(props.num_channels, 2);
+        assert_eq!(props.codec, 2);
+        assert_eq!(props.audio_packet_size(), 332);
+
+        let props = AudioFormat {
+            quality: AudioQuality::Normal,
+            channels: ChannelMode::Mono,
+            frame_size: FrameSize::Samples64,
+            block_size_factor: 1,
+            sequence_numbers: true,
+        }
+        .transport_props();
+        assert_eq!(props.coded_bytes(), 22);
+        assert_eq!(props.num_channels, 1);
+        assert_eq!(props.frame_size(), 64);
+        assert_eq!(props.audio_packet_size(), 23);
+
+        let props = AudioFormat {
+            sequence_numbers: false,
+            ..AudioFormat::default()
+        }
+        .transport_props();
+        assert_eq!(props.flags, 0);
+        assert_eq!(props.coded_bytes(), 165);
+        assert_eq!(props.audio_packet_size(), 330);
+
+        for (requested, used) in [(0, 1), (1, 1), (3, 2), (4, 4), (100, 4)] {
+            let props = AudioFormat {
+                block_size_factor: requested,
+                ..AudioFormat::default()
+            }
+            .transport_props();
+            assert_eq!(props.block_size_factor, used);
+        }
+    }
 }
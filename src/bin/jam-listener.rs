@@ -1,10 +1,16 @@
 use async_trait::async_trait;
 use clap::{App, Arg};
-use jamurust::{self, JamulusClient};
+use jamurust::protocol::{LicenceType, NetworkTransportProps, RecorderState, VersionInfo};
+use jamurust::{
+    self, ClientInfoChanged, ClientJoined, ClientLeft, JamulusClientBuilder, ReconnectPolicy,
+};
 use std::io::Write;
+use std::time::Duration;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -36,20 +42,62 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("listener")
                 .help("Client name"),
         )
+        .arg(
+            Arg::with_name("accept-licence")
+                .long("accept-licence")
+                .help("Accept the licence of servers that require one"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .default_value("10")
+                .help("Seconds without hearing from the server before reconnecting"),
+        )
         .arg(
             Arg::with_name("jsonrpcport")
                 .long("jsonrpcport")
                 .takes_value(true)
-                .help("Port for JSON RPC (WARNING: not functional, does not do anything useful whatsoever)"),
+                .help("Port for JSON RPC, for controlling the client while it runs"),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .takes_value(true)
+                .help(
+                    "Log level or filter, e.g. debug or jamurust=trace [default: RUST_LOG or info]",
+                ),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of the logs written to stderr"),
         )
         .get_matches();
 
+    // Logs go to stderr, as stdout carries the audio
+    let filter = match matches.value_of("log-level") {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    if matches.value_of("log-format") == Some("json") {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+
     // Bind a UDP socket
     let socket = UdpSocket::bind(matches.value_of("bind").unwrap()).await?;
     socket.connect(matches.value_of("server").unwrap()).await?;
 
     // Print the bound port
-    eprintln!("Bound to {}", socket.local_addr().unwrap());
+    info!("Bound to {}", socket.local_addr().unwrap());
 
     // Create a channel for receiving shutdown conditions
     let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel::<()>();
@@ -60,23 +108,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    let policy = ReconnectPolicy {
+        timeout: Duration::from_secs(matches.value_of("timeout").unwrap().parse()?),
+        ..ReconnectPolicy::default()
+    };
+
+    // Create a Jamulus client
+    let accept_licence = matches.is_present("accept-licence");
+    let handler = ClientHandler::new(shutdown_tx, accept_licence)?;
+    let mut client = JamulusClientBuilder::new(matches.value_of("name").unwrap())
+        .accept_licence(accept_licence)
+        .build(socket, handler);
+
     // If JSON-RPC port is specified, spawn a thread for handling JSON RPC
     if let Some(jsonrpc_port) = matches.value_of("jsonrpcport") {
         let jsonrpc_port = jsonrpc_port.parse::<u16>()?;
+        let handle = client.handle();
         tokio::spawn(async move {
-            if let Err(error) = jsonrpc::run(jsonrpc_port).await {
-                eprintln!("JSON RPC server error: {}", error);
+            if let Err(error) = jsonrpc::run(jsonrpc_port, handle).await {
+                error!("JSON RPC server error: {}", error);
             }
         });
     }
 
-    // Create a Jamulus client
-    let mut client = JamulusClient::new(
-        socket,
-        String::from(matches.value_of("name").unwrap()),
-        ClientHandler::new(shutdown_tx),
-    );
-    client.run(shutdown_condition).await;
+    match client.run_with_reconnect(shutdown_condition, policy).await {
+        Ok(()) => {}
+        Err(error @ jamurust::Error::Protocol(_)) if client.licence_required().is_some() => {
+            error!("{}. Pass --accept-licence to accept it.", error);
+            std::process::exit(1);
+        }
+        Err(error) => {
+            error!("{}", error);
+            std::process::exit(1);
+        }
+    }
     Ok(())
 }
 
@@ -85,50 +150,131 @@ struct ClientHandler {
     jitter_buffer: jamurust::jitter::JitterBuffer<Vec<u8>>,
     shutdown_tx: mpsc::UnboundedSender<()>,
     dead: bool,
+    accept_licence: bool,
 }
 impl ClientHandler {
-    fn new(shutdown_tx: mpsc::UnboundedSender<()>) -> Self {
-        ClientHandler {
-            audio_decoder: Mutex::new(jamurust::audio::Decoder::new()),
+    fn new(
+        shutdown_tx: mpsc::UnboundedSender<()>,
+        accept_licence: bool,
+    ) -> Result<Self, jamurust::Error> {
+        Ok(ClientHandler {
+            audio_decoder: Mutex::new(jamurust::audio::Decoder::new()?),
             jitter_buffer: jamurust::jitter::JitterBuffer::new(96),
             shutdown_tx,
             dead: false,
+            accept_licence,
+        })
+    }
+    /// Decodes a frame and writes it to stdout.
+    async fn play(&mut self, opus_packet: &[u8]) {
+        let mut output = [0 as i16; 1000];
+        let decoder = self.audio_decoder.lock().await;
+        let decoded = decoder.decode(opus_packet, &mut output);
+        let channels = decoder.channels();
+        drop(decoder);
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                warn!("Unable to decode audio: {}", error);
+                return;
+            }
+        };
+        // The output is always stereo: mono samples are written to both channels.
+        let mut samples = Vec::with_capacity(decoded * 2);
+        for value in output[..decoded * channels].iter() {
+            samples.push(*value);
+            if channels == 1 {
+                samples.push(*value);
+            }
+        }
+        for value in samples {
+            let b = value.to_le_bytes();
+            if let Err(err) = std::io::stdout().write_all(&b) {
+                error!("Error writing to stdout: {}", err);
+                // The shutdown receiver is gone if we are already shutting down.
+                let _ = self.shutdown_tx.send(());
+                self.dead = true;
+                return;
+            }
         }
     }
 }
 
 #[async_trait]
 impl jamurust::Handler for ClientHandler {
-    async fn handle_opus_packet(&mut self, packet: &[u8], sequence_number: u8) {
+    async fn handle_opus_packet(&mut self, packet: &[u8], sequence_number: Option<u8>) {
         if self.dead {
             return;
         }
-        if let Some(opus_packet) = self.jitter_buffer.put_in(packet.to_vec(), sequence_number) {
-            let mut output = [0 as i16; 1000];
-            let decoder = self.audio_decoder.lock().await;
-            let decoded = decoder.decode(&opus_packet, &mut output);
-            drop(decoder);
-            for value in output[..decoded * 2].iter() {
-                let b = value.to_le_bytes();
-                if let Err(err) = std::io::stdout().write_all(&b) {
-                    eprintln!("Error writing to stdout: {}", err);
-                    self.shutdown_tx.send(()).unwrap();
-                    self.dead = true;
-                    return;
-                }
+        let played_out = match sequence_number {
+            Some(sequence_number) => self.jitter_buffer.put_in(packet.to_vec(), sequence_number),
+            None => self.jitter_buffer.put_in_arrival_order(packet.to_vec()),
+        };
+        if let Some(opus_packet) = played_out {
+            self.play(&opus_packet).await;
+        }
+    }
+    async fn on_drain(&mut self) {
+        for opus_packet in self.jitter_buffer.drain() {
+            if self.dead {
+                return;
             }
+            self.play(&opus_packet).await;
         }
     }
     async fn handle_chat_text(&mut self, text: &str) {
-        eprintln!("Received chat message: {}", text);
+        info!("Received chat message: {}", text);
+    }
+    async fn on_licence_required(&mut self, licence_type: LicenceType) {
+        warn!(
+            "The server requires accepting its licence ({:?})",
+            licence_type
+        );
+        if !self.accept_licence {
+            // The shutdown receiver is gone if we are already shutting down.
+            let _ = self.shutdown_tx.send(());
+        }
+    }
+    async fn on_server_version(&mut self, version: &VersionInfo) {
+        info!(
+            "Server version is {} ({:?})",
+            version.version, version.os_type
+        );
+    }
+    async fn on_recorder_state(&mut self, state: RecorderState) {
+        if state == RecorderState::Recording {
+            info!("The server is recording");
+        }
+    }
+    async fn on_client_joined(&mut self, event: &ClientJoined) {
+        info!("{} joined", event.client.name);
+    }
+    async fn on_client_left(&mut self, event: &ClientLeft) {
+        info!("{} left", event.client.name);
+    }
+    async fn on_client_info_changed(&mut self, event: &ClientInfoChanged) {
+        if event.old.name != event.new.name {
+            info!("{} renamed to {}", event.old.name, event.new.name);
+        }
+    }
+    async fn on_own_channel_id(&mut self, channel_id: u8) {
+        info!("Channel ID is {}", channel_id);
+    }
+    async fn on_transport_props(&mut self, props: &NetworkTransportProps) {
+        match jamurust::audio::Decoder::from_transport_props(props) {
+            Ok(decoder) => *self.audio_decoder.lock().await = decoder,
+            Err(error) => error!("Unable to decode audio in this format: {}", error),
+        }
     }
 }
 
 mod jsonrpc {
+    use jamurust::{ChannelKey, ClientHandle};
     use serde::{Deserialize, Serialize};
     use serde_json::json;
     use tokio::io::AsyncBufReadExt;
     use tokio::io::AsyncWriteExt;
+    use tracing::warn;
 
     #[derive(Serialize, Deserialize, Debug)]
     struct Request {
@@ -137,15 +283,83 @@ mod jsonrpc {
         params: serde_json::Value,
     }
 
-    pub async fn run(jsonrpc_port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Deserialize)]
+    struct SendChatTextParams {
+        text: String,
+    }
+
+    /// Identifies a channel by `channel_id` or by client `name`.
+    #[derive(Deserialize)]
+    struct ChannelParams {
+        channel_id: Option<u8>,
+        name: Option<String>,
+    }
+    impl ChannelParams {
+        fn key(self) -> Result<ChannelKey, &'static str> {
+            match (self.channel_id, self.name) {
+                (Some(channel_id), _) => Ok(ChannelKey::Id(channel_id)),
+                (None, Some(name)) => Ok(ChannelKey::Name(name)),
+                (None, None) => Err("either channel_id or name is required"),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct SetChannelGainParams {
+        #[serde(flatten)]
+        channel: ChannelParams,
+        gain: u16,
+    }
+
+    #[derive(Deserialize)]
+    struct SetChannelPanParams {
+        #[serde(flatten)]
+        channel: ChannelParams,
+        pan: u16,
+    }
+
+    #[derive(Deserialize)]
+    struct SetChannelMutedParams {
+        #[serde(flatten)]
+        channel: ChannelParams,
+        muted: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct SetChannelSoloParams {
+        #[serde(flatten)]
+        channel: ChannelParams,
+        solo: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct SetExcludeOwnChannelParams {
+        exclude: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct SetChannelLevelsParams {
+        enabled: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct SetNameParams {
+        name: String,
+    }
+
+    pub async fn run(
+        jsonrpc_port: u16,
+        handle: ClientHandle,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // Create a TCP socket
         let jsonrpc_socket =
             tokio::net::TcpListener::bind(format!("127.0.0.1:{}", jsonrpc_port)).await?;
         loop {
             let (socket, _) = jsonrpc_socket.accept().await?;
+            let handle = handle.clone();
             tokio::spawn(async move {
-                if let Err(error) = run_json_rpc_connection(socket).await {
-                    eprintln!("JSON RPC connection error: {}", error);
+                if let Err(error) = run_json_rpc_connection(socket, handle).await {
+                    warn!("JSON RPC connection error: {}", error);
                 }
             });
         }
@@ -153,6 +367,7 @@ mod jsonrpc {
 
     async fn run_json_rpc_connection(
         mut socket: tokio::net::TcpStream,
+        handle: ClientHandle,
     ) -> Result<(), Box<dyn std::error::Error>> {
         loop {
             // Read a line from the socket
@@ -166,12 +381,12 @@ mod jsonrpc {
                     serde_json::Value::Array(array) => {
                         let mut output: Vec<serde_json::Value> = vec![];
                         for value in array {
-                            output.push(handle_request_and_serialize(value).await);
+                            output.push(handle_request_and_serialize(value, &handle).await);
                         }
                         send_json(&mut socket, &serde_json::Value::Array(output)).await?;
                     }
                     _ => {
-                        let response = handle_request_and_serialize(json).await;
+                        let response = handle_request_and_serialize(json, &handle).await;
                         send_json(&mut socket, &response).await?;
                     }
                 },
@@ -185,17 +400,85 @@ mod jsonrpc {
             }
         }
     }
-    async fn handle_request_and_serialize(json: serde_json::Value) -> serde_json::Value {
-        match handle_request(json).await {
+    async fn handle_request_and_serialize(
+        json: serde_json::Value,
+        handle: &ClientHandle,
+    ) -> serde_json::Value {
+        match handle_request(json, handle).await {
             Ok(result) => result,
             Err(error) => create_error(-32600, format!("Invalid request: {}", error), json!(null)),
         }
     }
     async fn handle_request(
         json: serde_json::Value,
+        handle: &ClientHandle,
     ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
         let request = serde_json::from_value::<Request>(json)?;
-        Ok(create_response(request.id, json!("UNIMPLEMENTED")))
+        match request.method.as_str() {
+            "sendChatText" => {
+                let params = serde_json::from_value::<SendChatTextParams>(request.params)?;
+                handle.send_chat_text(&params.text).await?;
+            }
+            "setChannelGain" => {
+                let params = serde_json::from_value::<SetChannelGainParams>(request.params)?;
+                handle
+                    .set_channel_gain(params.channel.key()?, params.gain)
+                    .await?;
+            }
+            "setChannelPan" => {
+                let params = serde_json::from_value::<SetChannelPanParams>(request.params)?;
+                handle
+                    .set_channel_pan(params.channel.key()?, params.pan)
+                    .await?;
+            }
+            "setChannelMuted" => {
+                let params = serde_json::from_value::<SetChannelMutedParams>(request.params)?;
+                handle
+                    .set_channel_muted(params.channel.key()?, params.muted)
+                    .await?;
+            }
+            "setChannelSolo" => {
+                let params = serde_json::from_value::<SetChannelSoloParams>(request.params)?;
+                handle
+                    .set_channel_solo(params.channel.key()?, params.solo)
+                    .await?;
+            }
+            "setExcludeOwnChannel" => {
+                let params = serde_json::from_value::<SetExcludeOwnChannelParams>(request.params)?;
+                handle.set_exclude_own_channel(params.exclude).await?;
+            }
+            "setName" => {
+                let params = serde_json::from_value::<SetNameParams>(request.params)?;
+                handle.set_name(&params.name).await?;
+            }
+            "setChannelLevels" => {
+                let params = serde_json::from_value::<SetChannelLevelsParams>(request.params)?;
+                handle.set_channel_levels(params.enabled).await?;
+            }
+            "getRoster" => {
+                return Ok(create_response(
+                    request.id,
+                    serde_json::to_value(handle.roster())?,
+                ));
+            }
+            "getState" => {
+                return Ok(create_response(
+                    request.id,
+                    serde_json::to_value(handle.state())?,
+                ));
+            }
+            "disconnect" => {
+                handle.disconnect().await?;
+            }
+            _ => {
+                return Ok(create_error(
+                    -32601,
+                    format!("Method not found: {}", request.method),
+                    request.id,
+                ));
+            }
+        }
+        Ok(create_response(request.id, json!(null)))
     }
     fn create_error(code: i32, message: String, id: serde_json::Value) -> serde_json::Value {
         json!({
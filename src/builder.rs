@@ -0,0 +1,131 @@
+use crate::audio::{self, AudioFormat, AudioQuality, ChannelMode, FrameSize};
+use crate::protocol::ChannelInfo;
+use crate::tables::{Country, Instrument};
+use crate::{Handler, JamulusClient};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// The version we report to servers in `VERSION_AND_OS`, unless configured otherwise.
+pub const DEFAULT_VERSION: &str = concat!("jamurust ", env!("CARGO_PKG_VERSION"));
+
+/// Configures a [`JamulusClient`] before connecting.
+///
+/// ```no_run
+/// # async fn example(socket: tokio::net::UdpSocket, handler: impl jamurust::Handler) {
+/// let client = jamurust::JamulusClientBuilder::new("listener")
+///     .country(jamurust::Country::Thailand)
+///     .city("Bangkok")
+///     .skill_level(1)
+///     .build(socket, handler);
+/// # }
+/// ```
+pub struct JamulusClientBuilder {
+    pub(crate) channel_info: ChannelInfo,
+    pub(crate) audio_format: AudioFormat,
+    pub(crate) channel_levels: bool,
+    pub(crate) accept_licence: bool,
+    pub(crate) version: String,
+    pub(crate) disconnect_timeout: Duration,
+}
+impl JamulusClientBuilder {
+    /// Creates a builder for a client with the given name, shown to other
+    /// clients as a listener.
+    pub fn new(name: impl Into<String>) -> Self {
+        JamulusClientBuilder {
+            channel_info: ChannelInfo {
+                country: Country::AnyCountry,
+                instrument: Instrument::Listener,
+                skill_level: 3,
+                name: name.into(),
+                city: String::new(),
+            },
+            audio_format: AudioFormat::default(),
+            channel_levels: false,
+            accept_licence: false,
+            version: String::from(DEFAULT_VERSION),
+            disconnect_timeout: Duration::from_secs(1),
+        }
+    }
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.channel_info.name = name.into();
+        self
+    }
+    pub fn country(mut self, country: Country) -> Self {
+        self.channel_info.country = country;
+        self
+    }
+    pub fn instrument(mut self, instrument: Instrument) -> Self {
+        self.channel_info.instrument = instrument;
+        self
+    }
+    /// 0 = not set, 1 = beginner, 2 = intermediate, 3 = expert.
+    pub fn skill_level(mut self, skill_level: u8) -> Self {
+        self.channel_info.skill_level = skill_level;
+        self
+    }
+    pub fn city(mut self, city: impl Into<String>) -> Self {
+        self.channel_info.city = city.into();
+        self
+    }
+    /// Replaces the whole channel identity at once.
+    pub fn channel_info(mut self, channel_info: ChannelInfo) -> Self {
+        self.channel_info = channel_info;
+        self
+    }
+    pub fn audio_quality(mut self, quality: AudioQuality) -> Self {
+        self.audio_format.quality = quality;
+        self
+    }
+    pub fn channel_mode(mut self, channels: ChannelMode) -> Self {
+        self.audio_format.channels = channels;
+        self
+    }
+    pub fn frame_size(mut self, frame_size: FrameSize) -> Self {
+        self.audio_format.frame_size = frame_size;
+        self
+    }
+    /// Number of frames per audio datagram: 1, 2 or 4, like in the reference
+    /// client. Other values are rounded down to one of these.
+    pub fn block_size_factor(mut self, block_size_factor: u16) -> Self {
+        self.audio_format.block_size_factor = audio::supported_block_size_factor(block_size_factor);
+        self
+    }
+    /// Asks for a sequence number after each frame (the default).
+    pub fn sequence_numbers(mut self, sequence_numbers: bool) -> Self {
+        self.audio_format.sequence_numbers = sequence_numbers;
+        self
+    }
+    /// Replaces the whole audio format at once.
+    pub fn audio_format(mut self, audio_format: AudioFormat) -> Self {
+        self.audio_format = audio_format;
+        self
+    }
+    /// Asks the server for the level of each channel (`CLM_CHANNEL_LEVEL_LIST`),
+    /// reported through `Handler::on_channel_levels`.
+    pub fn channel_levels(mut self, enabled: bool) -> Self {
+        self.channel_levels = enabled;
+        self
+    }
+    /// Accepts the licence of servers that require one (`LICENCE_REQUIRED`)
+    /// in advance. Otherwise, audio is held back until `accept_licence` is
+    /// called on the client or its handle.
+    pub fn accept_licence(mut self, accept: bool) -> Self {
+        self.accept_licence = accept;
+        self
+    }
+    /// The version string sent to the server in `VERSION_AND_OS`.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+    /// How long `run` may take to say goodbye to the server and play out
+    /// buffered audio (`Handler::on_drain`) before it returns. One second by default.
+    pub fn disconnect_timeout(mut self, timeout: Duration) -> Self {
+        self.disconnect_timeout = timeout;
+        self
+    }
+    /// Creates the client. The socket should already be connected to the server.
+    pub fn build<H: Handler>(self, socket: UdpSocket, handler: H) -> JamulusClient<H> {
+        JamulusClient::from_builder(self, socket, handler)
+    }
+}
@@ -0,0 +1,25 @@
+//! Where we are in the lifetime of a connection to the server.
+
+use serde::{Deserialize, Serialize};
+
+/// Reported through `Handler::on_state_change` and
+/// [`ClientHandle::state`](crate::ClientHandle::state).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Sending audio, but the server has not taken us in yet. Also the
+    /// state while waiting to reconnect.
+    Connecting,
+    /// The server sent `CLIENT_ID` or audio.
+    Connected,
+    /// The server answered with `CLM_SERVER_FULL`.
+    ServerFull,
+    /// Telling the server that we are leaving (`CLM_DISCONNECTION`).
+    Disconnecting,
+    /// `run` is about to return.
+    Disconnected,
+}
+impl ConnectionState {
+    pub fn is_connected(self) -> bool {
+        self == ConnectionState::Connected
+    }
+}
@@ -0,0 +1,248 @@
+//! Connectionless (`CLM_*`) messages over an unconnected UDP socket.
+//!
+//! These messages can be exchanged with any server without joining it,
+//! e.g. to measure the ping or to see who is currently connected.
+
+use crate::datagram;
+use crate::protocol::{ClientInfo, Message, ProtocolMessage, VersionInfo};
+use crate::Error;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tracing::debug;
+
+/// Large enough for any UDP datagram, as server lists can be big.
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+pub struct ConnectionlessSocket {
+    socket: UdpSocket,
+    epoch: Instant,
+}
+impl ConnectionlessSocket {
+    pub fn new(socket: UdpSocket) -> Self {
+        ConnectionlessSocket {
+            socket,
+            epoch: Instant::now(),
+        }
+    }
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::new(UdpSocket::bind(addr).await?))
+    }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends a connectionless message to the given address.
+    pub async fn send_to(&self, message: &ProtocolMessage, target: SocketAddr) -> io::Result<()> {
+        self.socket.send_to(&message.to_datagram(0), target).await?;
+        Ok(())
+    }
+
+    /// Receives the next protocol message, skipping audio and other datagrams
+    /// that are not protocol messages. Messages with a bad CRC (`Error::Crc`)
+    /// or a payload that cannot be parsed (`Error::Parse`) are returned as errors.
+    pub async fn recv_from(&self) -> Result<(ProtocolMessage, SocketAddr), Error> {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (n, source) = self.socket.recv_from(&mut buf).await?;
+            let datagram = &buf[..n];
+            if let Ok((_, msg)) = Message::parse(datagram) {
+                let message = ProtocolMessage::decode(msg.id, msg.data)?;
+                return Ok((message, source));
+            }
+            if datagram::is_protocol_framed(datagram) {
+                return Err(Error::Crc);
+            }
+        }
+    }
+
+    /// Measures the round trip time to a server.
+    pub async fn ping(&mut self, target: SocketAddr, timeout: Duration) -> io::Result<Duration> {
+        let time_ms = self.now_ms();
+        self.send_to(&ProtocolMessage::ClmPingMs { time_ms }, target)
+            .await?;
+        self.wait_for(target, timeout, |message| match message {
+            ProtocolMessage::ClmPingMs { time_ms: echoed } if echoed == time_ms => Some(()),
+            _ => None,
+        })
+        .await?;
+        Ok(self.elapsed_since(time_ms))
+    }
+
+    /// Measures the round trip time to a server, along with its number of connected clients.
+    pub async fn ping_with_num_clients(
+        &mut self,
+        target: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<(Duration, u8)> {
+        let time_ms = self.now_ms();
+        let request = ProtocolMessage::ClmPingMsWithNumClients {
+            time_ms,
+            num_clients: 0,
+        };
+        self.send_to(&request, target).await?;
+        let num_clients = self
+            .wait_for(target, timeout, |message| match message {
+                ProtocolMessage::ClmPingMsWithNumClients {
+                    time_ms: echoed,
+                    num_clients,
+                } if echoed == time_ms => Some(num_clients),
+                _ => None,
+            })
+            .await?;
+        Ok((self.elapsed_since(time_ms), num_clients))
+    }
+
+    /// Asks a server for its OS type and version.
+    pub async fn request_version_and_os(
+        &mut self,
+        target: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<VersionInfo> {
+        self.send_to(&ProtocolMessage::ClmRequestVersionAndOs, target)
+            .await?;
+        self.wait_for(target, timeout, |message| match message {
+            ProtocolMessage::ClmVersionAndOs { os_type, version } => {
+                Some(VersionInfo { os_type, version })
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    /// Asks a server for the list of its connected clients.
+    pub async fn request_client_list(
+        &mut self,
+        target: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<Vec<ClientInfo>> {
+        self.send_to(&ProtocolMessage::ClmRequestConnectedClientsList, target)
+            .await?;
+        self.wait_for(target, timeout, |message| match message {
+            ProtocolMessage::ClmConnectedClientsList { clients } => Some(clients),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits for a message from `target` that `accept` maps to a value,
+    /// ignoring everything else.
+    pub(crate) async fn wait_for<T>(
+        &self,
+        target: SocketAddr,
+        timeout: Duration,
+        mut accept: impl FnMut(ProtocolMessage) -> Option<T>,
+    ) -> io::Result<T> {
+        let receive = async {
+            loop {
+                let (message, source) = match self.recv_from().await {
+                    Ok(received) => received,
+                    Err(Error::Io(error)) => return Err(error),
+                    Err(error) => {
+                        debug!(%error, "Ignoring datagram");
+                        continue;
+                    }
+                };
+                if source != target {
+                    continue;
+                }
+                if let Some(value) = accept(message) {
+                    return Ok(value);
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, receive).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no response from {}", target),
+            )),
+        }
+    }
+    fn now_ms(&self) -> u32 {
+        self.epoch.elapsed().as_millis() as u32
+    }
+    fn elapsed_since(&self, time_ms: u32) -> Duration {
+        Duration::from_millis(self.now_ms().wrapping_sub(time_ms) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ping_with_num_clients_works() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            let (n, source) = server.recv_from(&mut buf).await.unwrap();
+            let (_, message) = ProtocolMessage::from_datagram(&buf[..n]).unwrap();
+            if let ProtocolMessage::ClmPingMsWithNumClients { time_ms, .. } = message {
+                let reply = ProtocolMessage::ClmPingMsWithNumClients {
+                    time_ms,
+                    num_clients: 3,
+                };
+                server.send_to(&reply.to_datagram(0), source).await.unwrap();
+            }
+        });
+
+        let mut socket = ConnectionlessSocket::bind("127.0.0.1:0").await.unwrap();
+        let (_, num_clients) = socket
+            .ping_with_num_clients(server_addr, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(num_clients, 3);
+    }
+
+    #[tokio::test]
+    async fn reports_undecodable_datagrams() {
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = ConnectionlessSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = socket.local_addr().unwrap();
+
+        let mut datagram = ProtocolMessage::ClmPingMs { time_ms: 1 }.to_datagram(0);
+        let last = datagram.len() - 1;
+        datagram[last] ^= 0xff;
+        sender.send_to(&datagram, target).await.unwrap();
+        assert!(matches!(socket.recv_from().await, Err(Error::Crc)));
+
+        // A ping with a truncated payload, but a valid CRC.
+        let truncated = Message {
+            id: ProtocolMessage::ClmPingMs { time_ms: 1 }.id(),
+            counter: 0,
+            data: &[1, 0],
+        };
+        sender.send_to(&truncated.to_bytes(), target).await.unwrap();
+        let id = truncated.id;
+        assert!(
+            matches!(socket.recv_from().await, Err(Error::Parse { id: parsed }) if parsed == id)
+        );
+
+        // Audio is skipped.
+        sender.send_to(&[0x04; 332], target).await.unwrap();
+        sender
+            .send_to(
+                &ProtocolMessage::ClmPingMs { time_ms: 2 }.to_datagram(0),
+                target,
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            socket.recv_from().await,
+            Ok((ProtocolMessage::ClmPingMs { time_ms: 2 }, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_times_out_without_response() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut socket = ConnectionlessSocket::bind("127.0.0.1:0").await.unwrap();
+        let result = socket
+            .request_version_and_os(server.local_addr().unwrap(), Duration::from_millis(50))
+            .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
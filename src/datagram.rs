@@ -0,0 +1,120 @@
+//! Telling protocol messages and audio apart.
+//!
+//! Protocol messages and audio share the same socket. A datagram is only
+//! treated as audio if it has the size negotiated in `NETW_TRANSPORT_PROPS`,
+//! so corrupted messages and stray datagrams never reach the decoder.
+
+use crate::protocol::{Message, NetworkTransportProps};
+
+/// Size of the protocol message header and CRC around the payload.
+const FRAMING_SIZE: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatagramKind {
+    /// A protocol message with a valid CRC.
+    Protocol,
+    /// Looks like a protocol message, but the CRC does not match.
+    BadCrc,
+    /// Audio with the negotiated packet size.
+    Audio,
+    /// Anything else.
+    Unknown,
+}
+
+/// Classifies a received datagram, given the negotiated audio properties.
+pub fn classify(datagram: &[u8], props: &NetworkTransportProps) -> DatagramKind {
+    if Message::parse(datagram).is_ok() {
+        DatagramKind::Protocol
+    } else if is_protocol_framed(datagram) {
+        DatagramKind::BadCrc
+    } else if is_audio_size(datagram.len(), props) {
+        DatagramKind::Audio
+    } else {
+        DatagramKind::Unknown
+    }
+}
+
+/// Audio packets carry `block_size_factor` frames, with or without sequence
+/// numbers: older servers ignore the flag and send plain frames.
+fn is_audio_size(len: usize, props: &NetworkTransportProps) -> bool {
+    len > 0
+        && (len == props.audio_packet_size()
+            || len == props.coded_bytes() * props.block_size_factor as usize)
+}
+
+pub(crate) fn is_protocol_framed(datagram: &[u8]) -> bool {
+    datagram.len() >= FRAMING_SIZE
+        && datagram[0..2] == [0x00, 0x00]
+        && u16::from_le_bytes([datagram[5], datagram[6]]) as usize + FRAMING_SIZE == datagram.len()
+}
+
+/// Number of datagrams received of each kind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatagramStats {
+    pub protocol: u64,
+    pub bad_crc: u64,
+    pub audio: u64,
+    pub unknown: u64,
+}
+impl DatagramStats {
+    pub fn record(&mut self, kind: DatagramKind) {
+        match kind {
+            DatagramKind::Protocol => self.protocol += 1,
+            DatagramKind::BadCrc => self.bad_crc += 1,
+            DatagramKind::Audio => self.audio += 1,
+            DatagramKind::Unknown => self.unknown += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AudioFormat;
+    use crate::protocol::ProtocolMessage;
+
+    #[test]
+    fn classifies_datagrams() {
+        let props = AudioFormat::default().transport_props();
+        let mut message = ProtocolMessage::ChatText {
+            text: String::from("hi"),
+        }
+        .to_datagram(0);
+        assert_eq!(classify(&message, &props), DatagramKind::Protocol);
+
+        let last = message.len() - 1;
+        message[last] ^= 0xff;
+        assert_eq!(classify(&message, &props), DatagramKind::BadCrc);
+
+        // A corrupted message that happens to have the size of an audio packet.
+        let mut message = ProtocolMessage::ChatText {
+            text: "a".repeat(321),
+        }
+        .to_datagram(0);
+        assert_eq!(message.len(), 332);
+        message[331] ^= 0xff;
+        assert_eq!(classify(&message, &props), DatagramKind::BadCrc);
+
+        assert_eq!(classify(&[0x04; 332], &props), DatagramKind::Audio);
+        assert_eq!(classify(&[0x04; 330], &props), DatagramKind::Audio);
+        assert_eq!(classify(&[0x04; 100], &props), DatagramKind::Unknown);
+        assert_eq!(classify(&[], &props), DatagramKind::Unknown);
+    }
+
+    #[test]
+    fn counts_datagrams() {
+        let mut stats = DatagramStats::default();
+        stats.record(DatagramKind::Audio);
+        stats.record(DatagramKind::Audio);
+        stats.record(DatagramKind::BadCrc);
+        assert_eq!(
+            stats,
+            DatagramStats {
+                protocol: 0,
+                bad_crc: 1,
+                audio: 2,
+                unknown: 0,
+            }
+        );
+    }
+}
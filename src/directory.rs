@@ -0,0 +1,198 @@
+//! Fetching the list of public servers from a Jamulus directory.
+
+use crate::connectionless::ConnectionlessSocket;
+use crate::protocol::{ProtocolMessage, ServerInfo};
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How often the request is repeated while waiting for the list, in case it was lost.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fetches the full server list (`CLM_SERVER_LIST`) from a directory.
+pub async fn fetch_server_list(
+    socket: &mut ConnectionlessSocket,
+    directory: SocketAddr,
+    timeout: Duration,
+) -> io::Result<Vec<ServerInfo>> {
+    fetch(
+        socket,
+        directory,
+        timeout,
+        ProtocolMessage::ClmRequestServerList,
+        |message| match message {
+            ProtocolMessage::ClmServerList { servers } => Some(servers),
+            _ => None,
+        },
+    )
+    .await
+}
+
+/// Fetches the reduced server list (`CLM_RED_SERVER_LIST`) from a directory.
+/// It is smaller and less likely to be fragmented, but only contains
+/// addresses and names.
+pub async fn fetch_reduced_server_list(
+    socket: &mut ConnectionlessSocket,
+    directory: SocketAddr,
+    timeout: Duration,
+) -> io::Result<Vec<ServerInfo>> {
+    fetch(
+        socket,
+        directory,
+        timeout,
+        ProtocolMessage::ClmRequestReducedServerList,
+        |message| match message {
+            ProtocolMessage::ClmReducedServerList { servers } => Some(servers),
+            _ => None,
+        },
+    )
+    .await
+}
+
+async fn fetch(
+    socket: &mut ConnectionlessSocket,
+    directory: SocketAddr,
+    timeout: Duration,
+    request: ProtocolMessage,
+    mut accept: impl FnMut(ProtocolMessage) -> Option<Vec<ServerInfo>>,
+) -> io::Result<Vec<ServerInfo>> {
+    let deadline = Instant::now() + timeout;
+    let mut servers = loop {
+        socket.send_to(&request, directory).await?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match socket
+            .wait_for(directory, remaining.min(REQUEST_INTERVAL), &mut accept)
+            .await
+        {
+            Ok(servers) => break servers,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut && Instant::now() < deadline => {}
+            Err(e) => return Err(e),
+        }
+    };
+
+    for server in servers.iter_mut() {
+        // The directory lists itself with an unspecified IP address.
+        if server.address.ip().is_unspecified() {
+            server.address.set_ip(directory.ip());
+        }
+    }
+
+    // While sending us the list, the directory asked each server to send us
+    // `CLM_EMPTY_MESSAGE` (via `CLM_SEND_EMPTY_MESSAGE`). Sending one back
+    // opens our side of the NAT, so that later pings get through. A server
+    // we cannot send to is still listed.
+    for server in servers.iter() {
+        if server.address != directory {
+            if let Err(e) = socket
+                .send_to(&ProtocolMessage::ClmEmptyMessage, server.address)
+                .await
+            {
+                debug!(address = %server.address, error = %e, "Unable to punch hole");
+            }
+        }
+    }
+
+    Ok(servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Country;
+    use tokio::net::UdpSocket;
+
+    /// Answers one `CLM_REQUEST_SERVER_LIST` with these servers.
+    async fn answer_with_list(directory: UdpSocket, servers: Vec<ServerInfo>) {
+        let mut buf = [0; 2048];
+        let (n, source) = directory.recv_from(&mut buf).await.unwrap();
+        let (_, request) = ProtocolMessage::from_datagram(&buf[..n]).unwrap();
+        assert_eq!(request, ProtocolMessage::ClmRequestServerList);
+        let list = ProtocolMessage::ClmServerList { servers };
+        directory
+            .send_to(&list.to_datagram(0), source)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn fetches_server_list_and_punches_holes() {
+        let directory = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let directory_addr = directory.local_addr().unwrap();
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        tokio::spawn(answer_with_list(
+            directory,
+            vec![
+                ServerInfo {
+                    address: SocketAddr::new([0, 0, 0, 0].into(), directory_addr.port()),
+                    name: String::from("Directory"),
+                    city: String::new(),
+                    country: Country::AnyCountry,
+                    max_clients: 0,
+                    permanent: true,
+                },
+                ServerInfo {
+                    address: server_addr,
+                    name: String::from("Server"),
+                    city: String::from("Bangkok"),
+                    country: Country::Thailand,
+                    max_clients: 10,
+                    permanent: false,
+                },
+            ],
+        ));
+
+        let mut socket = ConnectionlessSocket::bind("127.0.0.1:0").await.unwrap();
+        let servers = fetch_server_list(&mut socket, directory_addr, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].address, directory_addr);
+        assert_eq!(servers[1].name, "Server");
+
+        let mut buf = [0; 2048];
+        let (n, source) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(source, socket.local_addr().unwrap());
+        assert_eq!(
+            ProtocolMessage::from_datagram(&buf[..n]),
+            Some((0, ProtocolMessage::ClmEmptyMessage))
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_servers_that_cannot_be_reached() {
+        let directory = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let directory_addr = directory.local_addr().unwrap();
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let unsendable: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        let info = |address, name: &str| ServerInfo {
+            address,
+            name: String::from(name),
+            city: String::new(),
+            country: Country::AnyCountry,
+            max_clients: 10,
+            permanent: false,
+        };
+        tokio::spawn(answer_with_list(
+            directory,
+            vec![info(unsendable, "Broken"), info(server_addr, "Server")],
+        ));
+
+        let mut socket = ConnectionlessSocket::bind("127.0.0.1:0").await.unwrap();
+        let servers = fetch_server_list(&mut socket, directory_addr, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].address, unsendable);
+
+        let mut buf = [0; 2048];
+        let (n, _) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(
+            ProtocolMessage::from_datagram(&buf[..n]),
+            Some((0, ProtocolMessage::ClmEmptyMessage))
+        );
+    }
+}
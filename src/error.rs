@@ -0,0 +1,68 @@
+//! Errors that the client reports instead of printing them.
+
+use crate::protocol::DecodeError;
+use std::fmt;
+use std::io;
+
+/// An error reported through `Handler::on_error` or returned by the library.
+#[derive(Debug)]
+pub enum Error {
+    /// Sending or receiving on the socket failed.
+    Io(io::Error),
+    /// The payload of the message with this ID could not be parsed.
+    Parse { id: u16 },
+    /// A datagram looked like a protocol message, but its CRC did not match.
+    /// Returned by `ConnectionlessSocket::recv_from`; the client reports
+    /// these through `Handler::on_bad_crc` instead.
+    Crc,
+    /// Opus failed with this error code.
+    Opus(i32),
+    /// The server did not go along with the protocol, e.g. it required a
+    /// licence that was not accepted.
+    Protocol(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Parse { id } => write!(f, "unable to parse payload of message {}", id),
+            Error::Crc => write!(f, "received a message with a bad CRC"),
+            Error::Opus(code) => write!(f, "Opus error {}", code),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Error::Parse { id: error.id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_errors() {
+        let error = Error::from(DecodeError { id: 24 });
+        assert!(matches!(error, Error::Parse { id: 24 }));
+        assert_eq!(error.to_string(), "unable to parse payload of message 24");
+
+        let error = Error::from(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"));
+        assert!(matches!(&error, Error::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -0,0 +1,195 @@
+//! Controlling a running [`JamulusClient`](crate::JamulusClient) from elsewhere.
+
+use crate::connection::ConnectionState;
+use crate::mixer::ChannelKey;
+use crate::protocol::ChannelInfo;
+use crate::roster::Roster;
+use std::fmt;
+use tokio::sync::{mpsc, watch};
+
+pub(crate) enum Command {
+    SendChatText(String),
+    SetChannelGain { key: ChannelKey, gain: u16 },
+    SetChannelPan { key: ChannelKey, pan: u16 },
+    SetChannelMuted { key: ChannelKey, muted: bool },
+    SetChannelSolo { key: ChannelKey, solo: bool },
+    SetExcludeOwnChannel(bool),
+    SetChannelLevels(bool),
+    AcceptLicence,
+    RequestServerVersion,
+    SetName(String),
+    SetChannelInfo(ChannelInfo),
+    Disconnect,
+}
+
+/// A cheap, cloneable handle for sending commands to a running client.
+///
+/// Commands are queued and carried out by the client's `run` loop.
+#[derive(Clone)]
+pub struct ClientHandle {
+    commands: mpsc::UnboundedSender<Command>,
+    roster: watch::Receiver<Roster>,
+    state: watch::Receiver<ConnectionState>,
+}
+impl ClientHandle {
+    pub(crate) fn new(
+        commands: mpsc::UnboundedSender<Command>,
+        roster: watch::Receiver<Roster>,
+        state: watch::Receiver<ConnectionState>,
+    ) -> Self {
+        ClientHandle {
+            commands,
+            roster,
+            state,
+        }
+    }
+
+    /// Whether we are connected to the server.
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Waits until the connection state changes, then returns it.
+    pub async fn state_changed(&mut self) -> Result<ConnectionState, ClientClosed> {
+        self.state.changed().await.map_err(|_| ClientClosed)?;
+        Ok(self.state())
+    }
+
+    /// The clients currently connected and our own channel ID.
+    pub fn roster(&self) -> Roster {
+        self.roster.borrow().clone()
+    }
+
+    /// Waits until the roster changes, then returns it.
+    pub async fn roster_changed(&mut self) -> Result<Roster, ClientClosed> {
+        self.roster.changed().await.map_err(|_| ClientClosed)?;
+        Ok(self.roster())
+    }
+
+    /// Sends a chat message to everyone on the server.
+    pub async fn send_chat_text(&self, text: &str) -> Result<(), ClientClosed> {
+        self.send(Command::SendChatText(String::from(text)))
+    }
+
+    /// Sets the gain (0 to 0x8000) of a channel in our mix, by channel ID or client name.
+    pub async fn set_channel_gain(
+        &self,
+        key: impl Into<ChannelKey>,
+        gain: u16,
+    ) -> Result<(), ClientClosed> {
+        let key = key.into();
+        self.send(Command::SetChannelGain { key, gain })
+    }
+
+    /// Sets the pan (0 = left, 0x4000 = center, 0x8000 = right) of a channel in our mix.
+    pub async fn set_channel_pan(
+        &self,
+        key: impl Into<ChannelKey>,
+        pan: u16,
+    ) -> Result<(), ClientClosed> {
+        let key = key.into();
+        self.send(Command::SetChannelPan { key, pan })
+    }
+
+    pub async fn set_channel_muted(
+        &self,
+        key: impl Into<ChannelKey>,
+        muted: bool,
+    ) -> Result<(), ClientClosed> {
+        let key = key.into();
+        self.send(Command::SetChannelMuted { key, muted })
+    }
+
+    /// While any channel is soloed, only soloed channels are heard in our mix.
+    pub async fn set_channel_solo(
+        &self,
+        key: impl Into<ChannelKey>,
+        solo: bool,
+    ) -> Result<(), ClientClosed> {
+        let key = key.into();
+        self.send(Command::SetChannelSolo { key, solo })
+    }
+
+    /// Leaves our own channel out of our mix.
+    pub async fn set_exclude_own_channel(&self, exclude: bool) -> Result<(), ClientClosed> {
+        self.send(Command::SetExcludeOwnChannel(exclude))
+    }
+
+    /// Changes our name as shown to other clients.
+    pub async fn set_name(&self, name: &str) -> Result<(), ClientClosed> {
+        self.send(Command::SetName(String::from(name)))
+    }
+
+    /// Changes our whole channel identity (name, country, instrument, skill level and city).
+    pub async fn set_channel_info(&self, channel_info: ChannelInfo) -> Result<(), ClientClosed> {
+        self.send(Command::SetChannelInfo(channel_info))
+    }
+
+    /// Makes the client disconnect from the server and return from `run`.
+    pub async fn disconnect(&self) -> Result<(), ClientClosed> {
+        self.send(Command::Disconnect)
+    }
+
+    /// Accepts the server's licence, letting audio through.
+    pub async fn accept_licence(&self) -> Result<(), ClientClosed> {
+        self.send(Command::AcceptLicence)
+    }
+
+    /// Asks the server for its version, reported through `Handler::on_server_version`.
+    pub async fn request_server_version(&self) -> Result<(), ClientClosed> {
+        self.send(Command::RequestServerVersion)
+    }
+
+    /// Turns channel level updates on or off.
+    pub async fn set_channel_levels(&self, enabled: bool) -> Result<(), ClientClosed> {
+        self.send(Command::SetChannelLevels(enabled))
+    }
+
+    fn send(&self, command: Command) -> Result<(), ClientClosed> {
+        self.commands.send(command).map_err(|_| ClientClosed)
+    }
+}
+
+/// The client this handle belongs to has been dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientClosed;
+impl fmt::Display for ClientClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the client is no longer running")
+    }
+}
+impl std::error::Error for ClientClosed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn handle_reports_closed_client() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (_roster_sender, roster) = watch::channel(Roster::default());
+        let (_state_sender, state) = watch::channel(ConnectionState::Connecting);
+        let handle = ClientHandle::new(sender, roster, state);
+        handle.send_chat_text("hello").await.unwrap();
+        assert!(matches!(
+            receiver.recv().await,
+            Some(Command::SendChatText(text)) if text == "hello"
+        ));
+        drop(receiver);
+        assert_eq!(handle.clone().disconnect().await, Err(ClientClosed));
+    }
+
+    #[tokio::test]
+    async fn handle_follows_state() {
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let (_roster_sender, roster) = watch::channel(Roster::default());
+        let (state_sender, state) = watch::channel(ConnectionState::Connecting);
+        let mut handle = ClientHandle::new(sender, roster, state);
+        assert_eq!(handle.state(), ConnectionState::Connecting);
+
+        state_sender.send(ConnectionState::Connected).unwrap();
+        assert_eq!(handle.state_changed().await, Ok(ConnectionState::Connected));
+        drop(state_sender);
+        assert_eq!(handle.state_changed().await, Err(ClientClosed));
+    }
+}
@@ -1,7 +1,10 @@
+use tracing::{debug, trace};
+
 pub struct JitterBuffer<T> {
     size: usize,
     frames: Vec<Frame<T>>,
     latest_sequence_number: u8,
+    arrival_counter: u8,
 }
 
 struct Frame<T> {
@@ -15,12 +18,24 @@ impl<T> JitterBuffer<T> {
             size: size,
             frames: Vec::with_capacity(size),
             latest_sequence_number: 0,
+            arrival_counter: 0,
         }
     }
+    /// For frames without sequence numbers: frames are played out in the
+    /// order they arrive, delayed by the size of the buffer.
+    pub fn put_in_arrival_order(&mut self, frame: T) -> Option<T> {
+        let sequence_number = self.arrival_counter;
+        self.arrival_counter = self.arrival_counter.wrapping_add(1);
+        self.put_in(frame, sequence_number)
+    }
     pub fn put_in(&mut self, frame: T, sequence_number: u8) -> Option<T> {
         if self.frames.len() == self.size {
             // Pick the oldest frame and return it
             let latest_sequence_number = self.latest_sequence_number;
+            let expected = latest_sequence_number.wrapping_add(1);
+            if sequence_number != expected {
+                trace!(sequence_number, expected, "Frame out of order");
+            }
             let mut oldest_frame = self
                 .frames
                 .iter_mut()
@@ -43,6 +58,15 @@ impl<T> JitterBuffer<T> {
             None
         }
     }
+    /// Takes out all buffered frames, oldest first, e.g. to play them out
+    /// before shutting down.
+    pub fn drain(&mut self) -> Vec<T> {
+        debug!(frames = self.frames.len(), "Draining jitter buffer");
+        let latest_sequence_number = self.latest_sequence_number;
+        self.frames
+            .sort_by_key(|f| -Self::distance(latest_sequence_number, f.sequence_number));
+        self.frames.drain(..).filter_map(|f| f.payload).collect()
+    }
     fn distance(latest_sequence_number: u8, sequence_number: u8) -> i16 {
         let mut diff = (latest_sequence_number as i16) - (sequence_number as i16);
         if diff < -128 {
@@ -91,4 +115,25 @@ mod tests {
         assert_eq!(buffer.put_in("F", 2), Some("B"));
         assert_eq!(buffer.put_in("E", 1), Some("C"));
     }
+
+    #[test]
+    fn jitter_buffer_drains_oldest_first() {
+        let mut buffer = JitterBuffer::new(3);
+        assert_eq!(buffer.put_in("A", 254), None);
+        assert_eq!(buffer.put_in("B", 255), None);
+        assert_eq!(buffer.put_in("C", 0), None);
+        assert_eq!(buffer.put_in("D", 1), Some("A"));
+        assert_eq!(buffer.drain(), vec!["B", "C", "D"]);
+        assert_eq!(buffer.drain(), Vec::<&str>::new());
+        assert_eq!(buffer.put_in("E", 2), None);
+    }
+
+    #[test]
+    fn jitter_buffer_plays_out_in_arrival_order() {
+        let mut buffer = JitterBuffer::new(2);
+        for i in 0..300 {
+            let expected = if i < 2 { None } else { Some(i - 2) };
+            assert_eq!(buffer.put_in_arrival_order(i), expected);
+        }
+    }
 }
@@ -1,369 +1,1226 @@
 use async_trait::async_trait;
-use nom::IResult;
-use std::error::Error;
+use datagram::{DatagramKind, DatagramStats};
+use handle::Command;
+use mixer::Mixer;
+use protocol::{
+    ChannelInfo, ClientInfo, LicenceType, Message, NetworkTransportProps, OsType, ProtocolMessage,
+    RecorderState, VersionInfo,
+};
+use reconnect::Backoff;
+use reliable::{Outgoing, ReceivedMessages, SendQueue};
+use split::SplitAssembler;
 use std::future::Future;
-use std::io::Write;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, watch};
 use tokio::time::sleep;
+use tracing::{debug, info, info_span, trace, warn, Instrument as _};
+
+pub use builder::{JamulusClientBuilder, DEFAULT_VERSION};
+pub use connection::ConnectionState;
+pub use error::Error;
+pub use handle::{ClientClosed, ClientHandle};
+pub use mixer::ChannelKey;
+pub use reconnect::{ReconnectEvent, ReconnectPolicy, SessionEnd};
+pub use roster::{ClientEvent, ClientInfoChanged, ClientJoined, ClientLeft, Roster};
+pub use tables::{Country, Instrument};
 
 pub mod audio;
+mod builder;
+mod connection;
+pub mod connectionless;
 mod crc;
+pub mod datagram;
+pub mod directory;
+mod error;
+mod handle;
 pub mod jitter;
+mod mixer;
+pub mod protocol;
+mod reconnect;
+mod reliable;
+mod roster;
+mod split;
+pub mod tables;
+
+/// Maximum length of a chat message in characters.
+const MAX_CHAT_TEXT_LENGTH: usize = 1600;
+
+/// `CLM_DISCONNECTION` is not acknowledged, so it is sent a few times in
+/// case some get lost.
+const DISCONNECTION_ATTEMPTS: usize = 3;
+const DISCONNECTION_INTERVAL: Duration = Duration::from_millis(50);
 
 pub struct JamulusClient<H: Handler> {
-    name: String,
+    channel_info: ChannelInfo,
+    requested_transport_props: NetworkTransportProps,
+    transport_props: NetworkTransportProps,
+    datagram_stats: DatagramStats,
+    roster: Roster,
+    roster_sender: watch::Sender<Roster>,
+    roster_receiver: watch::Receiver<Roster>,
+    state: ConnectionState,
+    state_sender: watch::Sender<ConnectionState>,
+    state_receiver: watch::Receiver<ConnectionState>,
+    mixer: Mixer,
+    channel_levels: bool,
+    licence_accepted: bool,
+    licence_required: Option<LicenceType>,
+    version: String,
+    disconnect_timeout: Duration,
+    server_version: Option<VersionInfo>,
     socket: UdpSocket,
-    next_counter_id: u8,
+    send_queue: SendQueue,
+    received_messages: ReceivedMessages,
     handler: H,
-    shutting_down: bool,
+    session_end: Option<SessionEnd>,
+    timeout: Option<Duration>,
+    last_received: Instant,
+    reconnecting: bool,
+    split_supported: bool,
+    split_assembler: SplitAssembler,
+    command_sender: mpsc::UnboundedSender<Command>,
+    commands: mpsc::UnboundedReceiver<Command>,
 }
 impl<H: Handler> JamulusClient<H> {
     pub fn new(socket: UdpSocket, name: String, handler: H) -> Self {
+        JamulusClientBuilder::new(name).build(socket, handler)
+    }
+    pub(crate) fn from_builder(
+        builder: JamulusClientBuilder,
+        socket: UdpSocket,
+        handler: H,
+    ) -> Self {
+        let (command_sender, commands) = mpsc::unbounded_channel();
+        let (roster_sender, roster_receiver) = watch::channel(Roster::default());
+        let (state_sender, state_receiver) = watch::channel(ConnectionState::Connecting);
         JamulusClient {
-            name,
+            channel_info: builder.channel_info,
+            requested_transport_props: builder.audio_format.transport_props(),
+            transport_props: builder.audio_format.transport_props(),
+            datagram_stats: DatagramStats::default(),
+            roster: Roster::default(),
+            roster_sender,
+            roster_receiver,
+            state: ConnectionState::Connecting,
+            state_sender,
+            state_receiver,
+            mixer: Mixer::new(),
+            channel_levels: builder.channel_levels,
+            licence_accepted: builder.accept_licence,
+            licence_required: None,
+            version: builder.version,
+            disconnect_timeout: builder.disconnect_timeout,
+            server_version: None,
             socket,
-            next_counter_id: 1,
+            send_queue: SendQueue::new(),
+            received_messages: ReceivedMessages::new(),
             handler,
-            shutting_down: false,
+            session_end: None,
+            timeout: None,
+            last_received: Instant::now(),
+            reconnecting: false,
+            split_supported: false,
+            split_assembler: SplitAssembler::new(),
+            command_sender,
+            commands,
         }
     }
-    pub async fn run(&mut self, shutdown: impl Future) {
+    /// Runs the client until `shutdown` resolves, it is told to disconnect
+    /// or the server turns out to be full.
+    ///
+    /// Fails if the server required a licence that was never accepted.
+    pub async fn run(&mut self, shutdown: impl Future) -> Result<(), Error> {
         tokio::select! {
-            _ = self.communicate() => {}
+            _ = self.communicate().instrument(info_span!("session")) => {}
             _ = shutdown => {}
         }
+        self.disconnect().await
+    }
+
+    /// Like `run`, but starts a new session whenever the server stops
+    /// responding or is full, waiting longer after each failed attempt.
+    /// Our channel identity and mixer settings carry over to the new session.
+    pub async fn run_with_reconnect(
+        &mut self,
+        shutdown: impl Future,
+        policy: ReconnectPolicy,
+    ) -> Result<(), Error> {
+        tokio::pin!(shutdown);
+        self.timeout = Some(policy.timeout);
+        let mut backoff = Backoff::new(&policy);
+        loop {
+            let session = info_span!("session", attempt = backoff.attempt());
+            let end = tokio::select! {
+                end = self.communicate().instrument(session) => end,
+                _ = &mut shutdown => break,
+            };
+            if end == SessionEnd::Disconnected {
+                break;
+            }
+            if !self.reconnecting {
+                backoff.reset();
+            }
+            self.report_reconnect_event(ReconnectEvent::SessionEnded(end))
+                .await;
+            if matches!(policy.max_attempts, Some(max) if backoff.attempt() >= max) {
+                self.report_reconnect_event(ReconnectEvent::GaveUp).await;
+                break;
+            }
+
+            let delay = backoff.next_delay();
+            let attempt = backoff.attempt();
+            self.report_reconnect_event(ReconnectEvent::Reconnecting { attempt, delay })
+                .await;
+            let connect = tokio::select! {
+                connect = self.wait(delay) => connect,
+                _ = &mut shutdown => false,
+            };
+            if !connect {
+                break;
+            }
+            self.reset_session().await;
+        }
+        self.timeout = None;
+        self.disconnect().await
+    }
+
+    /// Says goodbye to the server and plays out buffered audio, giving up
+    /// after the configured `disconnect_timeout`. No audio is received
+    /// in the meantime.
+    #[tracing::instrument(level = "info", skip_all)]
+    async fn disconnect(&mut self) -> Result<(), Error> {
+        self.set_state(ConnectionState::Disconnecting).await;
+        self.session_end = Some(SessionEnd::Disconnected);
+        let timeout = self.disconnect_timeout;
+        let _ = tokio::time::timeout(timeout, self.leave()).await;
+        self.set_state(ConnectionState::Disconnected).await;
+
+        match self.licence_required {
+            Some(licence_type) if !self.licence_accepted => Err(Error::Protocol(format!(
+                "the server requires accepting its licence ({:?}), which was not accepted",
+                licence_type
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Says goodbye first, so that a slow drain cannot use up the deadline.
+    async fn leave(&mut self) {
+        for attempt in 0..DISCONNECTION_ATTEMPTS {
+            if attempt > 0 {
+                sleep(DISCONNECTION_INTERVAL).await;
+            }
+            self.send_message(ProtocolMessage::ClmDisconnection).await;
+        }
+        self.handler.on_drain().await;
+    }
+
+    /// Accepts the server's licence. Like in the reference client, nothing
+    /// is sent to the server: audio that was held back starts flowing.
+    pub fn accept_licence(&mut self) {
+        self.licence_accepted = true;
+    }
+    /// The server's version and OS, once it has told us.
+    pub fn server_version(&self) -> Option<&VersionInfo> {
+        self.server_version.as_ref()
+    }
+    /// Asks the server for its version (`CLM_REQ_VERSION_AND_OS`). The answer
+    /// is reported through `Handler::on_server_version`.
+    pub async fn request_server_version(&mut self) {
+        self.send_message(ProtocolMessage::ClmRequestVersionAndOs)
+            .await;
+    }
+
+    /// The licence the server asked us to accept, if any.
+    pub fn licence_required(&self) -> Option<LicenceType> {
+        self.licence_required
+    }
 
-        eprintln!("Disconnecting...");
-        self.shutting_down = true;
-        self.send_message(1010, &[]).await;
+    /// Sends a chat message to everyone on the server.
+    /// Texts longer than 1600 characters are truncated, like the reference client does.
+    pub async fn send_chat_text(&mut self, text: &str) {
+        let text = text.chars().take(MAX_CHAT_TEXT_LENGTH).collect();
+        self.send_message(ProtocolMessage::ChatText { text }).await;
     }
-    async fn communicate(&mut self) {
+
+    /// Changes our channel identity, updating it on the server right away.
+    pub async fn set_channel_info(&mut self, channel_info: ChannelInfo) {
+        self.channel_info = channel_info;
+        self.send_channel_infos().await;
+    }
+    pub fn channel_info(&self) -> &ChannelInfo {
+        &self.channel_info
+    }
+
+    /// Sets the gain (0 to 0x8000) of a channel in our mix, by channel ID or client name.
+    /// The setting is kept and applied again whenever the client list changes.
+    pub async fn set_channel_gain(&mut self, key: impl Into<ChannelKey>, gain: u16) {
+        self.mixer.set_gain(key.into(), gain);
+        self.apply_mixer().await;
+    }
+    /// Sets the pan (0 = left, 0x4000 = center, 0x8000 = right) of a channel in our mix.
+    pub async fn set_channel_pan(&mut self, key: impl Into<ChannelKey>, pan: u16) {
+        self.mixer.set_pan(key.into(), pan);
+        self.apply_mixer().await;
+    }
+    pub async fn set_channel_muted(&mut self, key: impl Into<ChannelKey>, muted: bool) {
+        self.mixer.set_muted(key.into(), muted);
+        self.apply_mixer().await;
+    }
+    /// While any channel is soloed, only soloed channels are heard in our mix.
+    pub async fn set_channel_solo(&mut self, key: impl Into<ChannelKey>, solo: bool) {
+        self.mixer.set_solo(key.into(), solo);
+        self.apply_mixer().await;
+    }
+    /// Leaves our own channel out of our mix.
+    pub async fn set_exclude_own_channel(&mut self, exclude: bool) {
+        self.mixer.set_exclude_own_channel(exclude);
+        self.apply_mixer().await;
+    }
+
+    /// Turns channel level updates (`Handler::on_channel_levels`) on or off.
+    pub async fn set_channel_levels(&mut self, enabled: bool) {
+        self.channel_levels = enabled;
+        self.send_message(ProtocolMessage::RequestChannelLevelList { enabled })
+            .await;
+    }
+
+    /// The properties of the audio stream, as negotiated with the server.
+    pub fn transport_props(&self) -> &NetworkTransportProps {
+        &self.transport_props
+    }
+
+    /// Number of datagrams received so far, by kind.
+    pub fn datagram_stats(&self) -> &DatagramStats {
+        &self.datagram_stats
+    }
+
+    /// The clients currently connected and our own channel ID.
+    pub fn roster(&self) -> &Roster {
+        &self.roster
+    }
+
+    /// Whether we are connected to the server.
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Returns a handle for controlling this client while `run` is in progress.
+    pub fn handle(&self) -> ClientHandle {
+        ClientHandle::new(
+            self.command_sender.clone(),
+            self.roster_receiver.clone(),
+            self.state_receiver.clone(),
+        )
+    }
+    /// Keeps carrying out commands while waiting to reconnect. Returns
+    /// `false` if told to disconnect in the meantime.
+    async fn wait(&mut self, delay: Duration) -> bool {
+        let wake_up = sleep(delay);
+        tokio::pin!(wake_up);
+        while self.session_end != Some(SessionEnd::Disconnected) {
+            tokio::select! {
+                _ = &mut wake_up => return true,
+                Some(command) = self.commands.recv() => {
+                    self.handle_command(command).await;
+                }
+            }
+        }
+        false
+    }
+    /// Forgets everything about the previous session, except for our own
+    /// settings and the client list, so that only actual changes are
+    /// reported when the server sends the list again. Messages that were
+    /// never acknowledged are reported as failed.
+    async fn reset_session(&mut self) {
+        self.transport_props = self.requested_transport_props.clone();
+        for message in self.send_queue.take_pending() {
+            self.handler.on_send_failed(&message).await;
+        }
+        self.received_messages = ReceivedMessages::new();
+        self.split_supported = false;
+        self.split_assembler = SplitAssembler::new();
+        self.licence_required = None;
+        self.server_version = None;
+        self.mixer.forget_sent();
+        self.roster = Roster {
+            clients: std::mem::take(&mut self.roster.clients),
+            ..Roster::default()
+        };
+        self.publish_roster();
+        self.set_state(ConnectionState::Connecting).await;
+        self.session_end = None;
+        self.last_received = Instant::now();
+        self.reconnecting = true;
+    }
+    /// Called whenever the server shows that it has taken us in.
+    async fn session_established(&mut self) {
+        self.set_state(ConnectionState::Connected).await;
+        if self.reconnecting {
+            self.reconnecting = false;
+            self.report_reconnect_event(ReconnectEvent::Reconnected)
+                .await;
+        }
+    }
+    async fn communicate(&mut self) -> SessionEnd {
         let mut silence = SilentOpusStream::new();
         let mut send_interval = tokio::time::interval(Duration::from_millis(100));
+        let mut retransmit_interval = tokio::time::interval(Duration::from_millis(100));
 
-        while !self.shutting_down {
+        self.last_received = Instant::now();
+        loop {
+            if let Some(end) = self.session_end {
+                return end;
+            }
             let mut buf = [0; 2048];
             tokio::select! {
                 recv_result = self.socket.recv(&mut buf) => {
                     match recv_result {
                         Ok(n) => {
+                            self.last_received = Instant::now();
                             self.handle_packet(&buf[..n]).await;
                         }
-                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                            eprintln!("Timed out");
-                        }
-                        Err(e) => {
-                            eprintln!("Unable to receive: {}", e);
+                        Err(error) => {
+                            // E.g. the server is not running: try again later.
+                            self.report_error(Error::Io(error)).await;
                             sleep(Duration::from_millis(100)).await;
                         }
                     }
                 }
                 _ = send_interval.tick() => {
-                    if let Err(e) = self.socket.send(&silence.next()[..]).await {
-                        eprintln!("Unable to send audio data: {}", e);
+                    let elapsed = self.last_received.elapsed();
+                    if matches!(self.timeout, Some(timeout) if elapsed >= timeout) {
+                        warn!(?elapsed, "No datagrams from the server");
+                        self.session_end = Some(SessionEnd::TimedOut);
+                        self.set_state(ConnectionState::Connecting).await;
+                        continue;
+                    }
+                    let packet = silence.next(&self.transport_props);
+                    if let Err(error) = self.socket.send(&packet).await {
+                        self.report_error(Error::Io(error)).await;
                     }
                 }
+                _ = retransmit_interval.tick() => {
+                    self.flush_send_queue().await;
+                }
+                Some(command) = self.commands.recv() => {
+                    self.handle_command(command).await;
+                }
+            }
+        }
+    }
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::SendChatText(text) => {
+                self.send_chat_text(&text).await;
+            }
+            Command::SetChannelGain { key, gain } => {
+                self.set_channel_gain(key, gain).await;
+            }
+            Command::SetChannelPan { key, pan } => {
+                self.set_channel_pan(key, pan).await;
+            }
+            Command::SetChannelMuted { key, muted } => {
+                self.set_channel_muted(key, muted).await;
+            }
+            Command::SetChannelSolo { key, solo } => {
+                self.set_channel_solo(key, solo).await;
+            }
+            Command::SetExcludeOwnChannel(exclude) => {
+                self.set_exclude_own_channel(exclude).await;
+            }
+            Command::SetChannelLevels(enabled) => {
+                self.set_channel_levels(enabled).await;
+            }
+            Command::AcceptLicence => {
+                self.accept_licence();
+            }
+            Command::RequestServerVersion => {
+                self.request_server_version().await;
+            }
+            Command::SetName(name) => {
+                self.channel_info.name = name;
+                self.send_channel_infos().await;
+            }
+            Command::SetChannelInfo(channel_info) => {
+                self.set_channel_info(channel_info).await;
+            }
+            Command::Disconnect => {
+                self.session_end = Some(SessionEnd::Disconnected);
             }
         }
     }
     async fn handle_packet(&mut self, payload: &[u8]) {
-        match Message::parse(payload) {
-            Ok((_, msg)) => {
-                if let Err(e) = self.handle_chat_text(msg).await {
-                    eprintln!("Unable to handle message: {}", e);
+        let kind = datagram::classify(payload, &self.transport_props);
+        self.datagram_stats.record(kind);
+        match kind {
+            DatagramKind::Protocol => {
+                if let Ok((_, msg)) = Message::parse(payload) {
+                    if let Err(error) = self.handle_message(msg).await {
+                        self.report_error(error).await;
+                    }
                 }
             }
-            Err(_e) => {
-                self.handle_audio_packet(payload).await;
+            DatagramKind::Audio => {
+                // Audio is held back until the licence is accepted.
+                self.session_established().await;
+                if self.licence_required.is_none() || self.licence_accepted {
+                    self.handle_audio_packet(payload).await;
+                }
+            }
+            DatagramKind::BadCrc => {
+                debug!(len = payload.len(), "Dropping datagram with bad CRC");
+                self.handler.on_bad_crc(payload).await;
+            }
+            DatagramKind::Unknown => {
+                debug!(len = payload.len(), "Dropping unknown datagram");
+                self.handler.on_unknown_datagram(payload).await;
             }
         }
     }
-    async fn handle_chat_text<'a>(&mut self, msg: Message<'a>) -> Result<(), Box<dyn Error>> {
-        eprintln!("Received {:?}", msg);
-
-        match msg.id {
-            32 => {
-                // Client ID
-                let channel_id = msg.data[0];
-                eprintln!("Channel ID is {}", channel_id);
-            }
-            34 => {
-                // Request split message support
+    #[tracing::instrument(level = "trace", skip_all, fields(id = msg.id, counter = msg.counter))]
+    async fn handle_message(&mut self, msg: Message<'_>) -> Result<(), Error> {
+        if msg.id != 1 && !protocol::is_connectionless_id(msg.id) {
+            // Send acknowledgement
+            let ack = ProtocolMessage::Acknowledgement { message_id: msg.id };
+            if let Err(error) = self.socket.send(&ack.to_datagram(msg.counter)).await {
+                self.report_error(Error::Io(error)).await;
             }
-            24 => {
-                // Client list
-                match ClientInfo::parse_all(msg.data) {
-                    Ok(clients) => {
-                        eprintln!("Clients: {:?}", clients);
-
-                        // Unmute each client
-                        for client in clients {
-                            let mut bytes = Vec::with_capacity(3);
 
-                            // Client ID
-                            bytes.write(&(client.channel_id).to_le_bytes())?;
-
-                            // Gain
-                            bytes.write(&(0x8000 as u16).to_le_bytes())?;
+            // A retransmission, because our acknowledgement was lost
+            if !self.received_messages.insert(msg.id, msg.counter) {
+                return Ok(());
+            }
+        }
 
-                            debug_assert_eq!(bytes.len(), 3);
+        match ProtocolMessage::decode(msg.id, msg.data)? {
+            ProtocolMessage::Acknowledgement { message_id } => {
+                if self.send_queue.acknowledge(message_id, msg.counter) {
+                    self.flush_send_queue().await;
+                }
+            }
+            ProtocolMessage::SplitMessage {
+                message_id,
+                num_parts,
+                part_index,
+                data,
+            } => {
+                if let Some((id, payload)) = self
+                    .split_assembler
+                    .put(message_id, num_parts, part_index, data)
+                {
+                    let message = ProtocolMessage::decode(id, &payload)?;
+                    self.dispatch_message(message).await;
+                }
+            }
+            message => {
+                self.dispatch_message(message).await;
+            }
+        }
 
-                            self.send_message(13, &bytes).await;
+        Ok(())
+    }
+    async fn dispatch_message(&mut self, message: ProtocolMessage) {
+        debug!(?message, "Dispatching message");
+        match message {
+            ProtocolMessage::ClientId { channel_id } => {
+                self.session_established().await;
+                self.roster.own_channel_id = Some(channel_id);
+                self.publish_roster();
+                self.handler.on_own_channel_id(channel_id).await;
+                self.send_message(ProtocolMessage::VersionAndOs {
+                    os_type: OsType::current(),
+                    version: self.version.clone(),
+                })
+                .await;
+                self.apply_mixer().await;
+                if self.channel_levels {
+                    self.send_message(ProtocolMessage::RequestChannelLevelList { enabled: true })
+                        .await;
+                }
+            }
+            ProtocolMessage::LicenceRequired { licence_type }
+                if licence_type != LicenceType::NoLicence =>
+            {
+                self.licence_required = Some(licence_type);
+                self.handler.on_licence_required(licence_type).await;
+            }
+            ProtocolMessage::VersionAndOs { os_type, version }
+            | ProtocolMessage::ClmVersionAndOs { os_type, version } => {
+                let server_version = VersionInfo { os_type, version };
+                self.handler.on_server_version(&server_version).await;
+                self.server_version = Some(server_version);
+            }
+            ProtocolMessage::MuteStateChanged { channel_id, muted } => {
+                self.roster.set_muted_by(channel_id, muted);
+                self.publish_roster();
+                self.handler.on_mute_state_changed(channel_id, muted).await;
+            }
+            ProtocolMessage::RecorderState { state } => {
+                self.roster.recorder_state = Some(state);
+                self.publish_roster();
+                self.handler.on_recorder_state(state).await;
+            }
+            ProtocolMessage::ClmChannelLevelList { levels } => {
+                self.roster.set_levels(&levels);
+                self.publish_roster();
+                self.handler.on_channel_levels(&self.roster.levels).await;
+            }
+            ProtocolMessage::ClmServerFull => {
+                warn!("The server is full");
+                self.session_end = Some(SessionEnd::ServerFull);
+                self.set_state(ConnectionState::ServerFull).await;
+            }
+            ProtocolMessage::RequestSplitMessageSupport => {
+                self.split_supported = true;
+                self.send_message(ProtocolMessage::SplitMessageSupported)
+                    .await;
+            }
+            ProtocolMessage::ConnectedClientsList { clients } => {
+                let events = roster::diff(&self.roster.clients, &clients, SystemTime::now());
+                self.roster.set_clients(clients);
+                self.publish_roster();
+                self.handler.on_client_list(&self.roster.clients).await;
+                for event in events {
+                    match event {
+                        ClientEvent::Joined(event) => self.handler.on_client_joined(&event).await,
+                        ClientEvent::Left(event) => self.handler.on_client_left(&event).await,
+                        ClientEvent::InfoChanged(event) => {
+                            self.handler.on_client_info_changed(&event).await
                         }
                     }
-                    Err(e) => {
-                        eprintln!("Unable to parse client list: {}", e);
-                    }
                 }
+                self.apply_mixer().await;
             }
-            21 => {
-                // Request network properties
-                let mut bytes = Vec::with_capacity(19);
-
-                // Packet size
-                bytes.write(&(166 as u32).to_le_bytes()).unwrap();
-
-                // Block size
-                bytes.write(&(2 as u16).to_le_bytes()).unwrap();
-
-                // Stereo
-                bytes.write(&(2 as u8).to_le_bytes()).unwrap();
-
-                // Sample rate
-                bytes.write(&(48000 as u32).to_le_bytes()).unwrap();
-
-                // Codec: Opus
-                bytes.write(&(2 as u16).to_le_bytes()).unwrap();
-
-                // Flags: Add sequence number
-                bytes.write(&(1 as u16).to_le_bytes()).unwrap();
-
-                // Codec options (none)
-                bytes.write(&(0 as u32).to_le_bytes()).unwrap();
-
-                debug_assert_eq!(bytes.len(), 19);
-                self.send_message(20, &bytes).await;
-            }
-            11 => {
-                // Request jitter buffer size
-                self.send_message(10, &(4 as u16).to_le_bytes()).await;
+            ProtocolMessage::RequestNetworkTransportProps => {
+                let props = self.transport_props.clone();
+                self.send_message(ProtocolMessage::NetworkTransportProps(props.clone()))
+                    .await;
+                self.handler.on_transport_props(&props).await;
             }
-            23 => {
-                // Request channel info
-                let mut bytes = Vec::new();
-
-                // Country
-                bytes.write(&(0 as u16).to_le_bytes()).unwrap();
-
-                // Instrument: Listener
-                bytes.write(&(25 as u32).to_le_bytes()).unwrap();
-
-                // Skill Level
-                bytes.write(&(3 as u8).to_le_bytes()).unwrap();
-
-                // Name
-                bytes
-                    .write(&(self.name.len() as u16).to_le_bytes())
-                    .unwrap();
-                bytes.write(self.name.as_bytes()).unwrap();
-
-                // City
-                let city = "";
-                bytes.write(&(city.len() as u16).to_le_bytes()).unwrap();
-                bytes.write(city.as_bytes()).unwrap();
-
-                self.send_message(25, &bytes).await;
+            ProtocolMessage::NetworkTransportProps(props)
+                if props.coded_bytes() > 0 && props.block_size_factor > 0 =>
+            {
+                // The server tells us how it will send audio: follow it.
+                self.transport_props = props;
+                self.handler.on_transport_props(&self.transport_props).await;
             }
-            18 => {
-                self.handler
-                    .handle_chat_text(std::str::from_utf8(&msg.data[2..])?)
+            ProtocolMessage::RequestJitterBufferSize => {
+                self.send_message(ProtocolMessage::JitterBufferSize { blocks: 4 })
                     .await;
             }
+            ProtocolMessage::RequestChannelInfos => {
+                self.send_channel_infos().await;
+            }
+            ProtocolMessage::ChatText { text } => {
+                self.handler.handle_chat_text(&text).await;
+            }
             _ => {}
         }
-
-        if msg.id != 1 && msg.id < 1000 {
-            // Send acknowledgement
-            let ack = Message {
-                id: 1,
-                counter: msg.counter,
-                data: &msg.id.to_le_bytes(),
-            };
-            if let Err(error) = self.socket.send(&ack.to_bytes()).await {
-                eprintln!("Unable to send acknowledgement packet: {}", error);
-            }
+    }
+    async fn report_error(&mut self, error: Error) {
+        warn!(%error, "Recovering from error");
+        self.handler.on_error(&error).await;
+    }
+    async fn report_reconnect_event(&mut self, event: ReconnectEvent) {
+        info!(?event, "Reconnection");
+        self.handler.on_reconnect_event(&event).await;
+    }
+    async fn set_state(&mut self, state: ConnectionState) {
+        if state == self.state {
+            return;
         }
-
-        Ok(())
+        info!(from = ?self.state, to = ?state, "Connection state changed");
+        self.state = state;
+        // Cannot fail: we hold receivers for the state and the roster ourselves.
+        let _ = self.state_sender.send(state);
+        self.handler.on_state_change(state).await;
+    }
+    fn publish_roster(&mut self) {
+        let _ = self.roster_sender.send(self.roster.clone());
     }
-    async fn send_message(&mut self, message_id: u16, data: &[u8]) {
-        let datagram = Message {
-            id: message_id,
-            counter: self.next_counter_id,
-            data: data,
+    async fn apply_mixer(&mut self) {
+        let roster = &self.roster;
+        let messages = self.mixer.messages(&roster.clients, roster.own_channel_id);
+        for message in messages {
+            self.send_message(message).await;
+        }
+    }
+    async fn send_channel_infos(&mut self) {
+        let channel_info = self.channel_info.clone();
+        self.send_message(ProtocolMessage::ChannelInfos(channel_info))
+            .await;
+    }
+    async fn send_message(&mut self, message: ProtocolMessage) {
+        if message.is_connectionless() {
+            self.send_datagram(&message, 0).await;
+            return;
+        }
+        let parts = if self.split_supported {
+            split::split_message(message)
+        } else {
+            vec![message]
         };
-        self.next_counter_id = self.next_counter_id.wrapping_add(1);
-        if let Err(error) = self.socket.send(&datagram.to_bytes()).await {
-            eprintln!(
-                "Unable to send message {} with counter {}: {}",
-                datagram.id, datagram.counter, error
-            );
+        for part in parts {
+            self.send_queue.push(part);
+        }
+        self.flush_send_queue().await;
+    }
+    async fn flush_send_queue(&mut self) {
+        while let Some(outgoing) = self.send_queue.poll(Instant::now()) {
+            match outgoing {
+                Outgoing::Send(datagram) => {
+                    if let Err(error) = self.socket.send(&datagram).await {
+                        self.report_error(Error::Io(error)).await;
+                    }
+                }
+                Outgoing::Failed(message) => {
+                    warn!(id = message.id(), "Message was never acknowledged");
+                    self.handler.on_send_failed(&message).await;
+                }
+            }
+        }
+    }
+    async fn send_datagram(&mut self, message: &ProtocolMessage, counter: u8) {
+        if let Err(error) = self.socket.send(&message.to_datagram(counter)).await {
+            self.report_error(Error::Io(error)).await;
         }
     }
     async fn handle_audio_packet(&mut self, packet: &[u8]) {
-        if packet.len() == 332 {
-            self.handler
-                .handle_opus_packet(&packet[0..165], packet[165])
-                .await;
+        let props = &self.transport_props;
+        let coded_bytes = props.coded_bytes();
+        let has_sequence_numbers =
+            props.has_sequence_numbers() && packet.len() == props.audio_packet_size();
+        let frame_size = coded_bytes + has_sequence_numbers as usize;
+        trace!(len = packet.len(), has_sequence_numbers, "Received audio");
+        for frame in packet.chunks(frame_size) {
+            let sequence_number = if has_sequence_numbers {
+                Some(frame[coded_bytes])
+            } else {
+                None
+            };
             self.handler
-                .handle_opus_packet(&packet[166..331], packet[331])
+                .handle_opus_packet(&frame[..coded_bytes], sequence_number)
                 .await;
-        } else {
-            eprintln!("Received unknown packet of length {}", packet.len());
         }
     }
 }
 
 #[async_trait]
 pub trait Handler: Send + Sync {
-    async fn handle_opus_packet(&mut self, _packet: &[u8], _sequence_number: u8) {}
+    /// Called for each coded audio frame. The sequence number is only
+    /// present if the negotiated transport props ask for it.
+    async fn handle_opus_packet(&mut self, _packet: &[u8], _sequence_number: Option<u8>) {}
     async fn handle_chat_text(&mut self, _text: &str) {}
+    /// Called when a message was never acknowledged by the server,
+    /// despite being sent several times, or when the session ended before
+    /// it was.
+    async fn on_send_failed(&mut self, _message: &ProtocolMessage) {}
+    /// Called when the audio stream properties are sent to or received from
+    /// the server. Opus packets that follow are in this format.
+    async fn on_transport_props(&mut self, _props: &NetworkTransportProps) {}
+    /// Called for a datagram that looks like a protocol message but fails
+    /// the CRC check. It is dropped.
+    async fn on_bad_crc(&mut self, _datagram: &[u8]) {}
+    /// Called with the full client list whenever it changes (`CONN_CLIENTS_LIST`).
+    async fn on_client_list(&mut self, _clients: &[ClientInfo]) {}
+    /// Called when the server tells us our own channel ID (`CLIENT_ID`).
+    async fn on_own_channel_id(&mut self, _channel_id: u8) {}
+    /// Called with `(channel_id, level)` pairs whenever the server sends
+    /// channel levels. Levels must be enabled with `channel_levels` first.
+    async fn on_channel_levels(&mut self, _levels: &[(u8, u8)]) {}
+    /// Called when the server requires accepting its licence. Until
+    /// `accept_licence` is called, no audio is passed to the handler.
+    async fn on_licence_required(&mut self, _licence_type: LicenceType) {}
+    /// Called when the server tells us its version, after connecting or
+    /// when asked with `request_server_version`.
+    async fn on_server_version(&mut self, _version: &VersionInfo) {}
+    /// Called when another client mutes or unmutes us in their mix.
+    async fn on_mute_state_changed(&mut self, _channel_id: u8, _muted: bool) {}
+    /// Called when the server starts or stops recording.
+    async fn on_recorder_state(&mut self, _state: RecorderState) {}
+    /// Called after `on_client_list` for each client that appeared in the list.
+    async fn on_client_joined(&mut self, _event: &ClientJoined) {}
+    /// Called after `on_client_list` for each client that disappeared from the list.
+    async fn on_client_left(&mut self, _event: &ClientLeft) {}
+    /// Called after `on_client_list` for each client whose information changed.
+    async fn on_client_info_changed(&mut self, _event: &ClientInfoChanged) {}
+    /// Called for a datagram that is neither a protocol message nor audio
+    /// of the negotiated size. It is dropped.
+    async fn on_unknown_datagram(&mut self, _datagram: &[u8]) {}
+    /// Called when the connection state changes, e.g. to `Connected` once
+    /// the server has sent `CLIENT_ID` or audio.
+    async fn on_state_change(&mut self, _state: ConnectionState) {}
+    /// Called while disconnecting, once no more audio is coming: the time
+    /// to play out audio that is still buffered, e.g. in a `JitterBuffer`.
+    async fn on_drain(&mut self) {}
+    /// Called for errors that the client recovers from by itself, such as
+    /// a failed send or a message that cannot be parsed. The library never
+    /// prints them.
+    async fn on_error(&mut self, _error: &Error) {}
+    /// Called as `run_with_reconnect` loses the server and reconnects.
+    async fn on_reconnect_event(&mut self, _event: &ReconnectEvent) {}
 }
 
-#[derive(Debug)]
-struct Message<'a> {
-    id: u16,
+struct SilentOpusStream {
     counter: u8,
-    data: &'a [u8],
 }
-impl Message<'_> {
-    fn parse<'a>(input_bytes: &'a [u8]) -> IResult<&'a [u8], Message<'a>> {
-        // Use `nom` to parse the message.
-        // All numbers are in little endian.
-        let bytes = input_bytes;
-
-        // First two bytes are 0x00 0x00.
-        let (bytes, _) = nom::bytes::complete::tag([0x00, 0x00])(bytes)?;
-
-        // Next two bytes are the message ID.
-        let (bytes, id) = nom::number::complete::le_u16(bytes)?;
-
-        // The next byte is the counter.
-        let (bytes, counter) = nom::number::complete::le_u8(bytes)?;
+impl SilentOpusStream {
+    pub fn new() -> Self {
+        SilentOpusStream { counter: 0 }
+    }
+    pub fn next(&mut self, props: &NetworkTransportProps) -> Vec<u8> {
+        let mut packet = vec![0; props.audio_packet_size()];
+        for frame in packet.chunks_mut(props.base_packet_size as usize) {
+            self.write(frame, props.has_sequence_numbers());
+        }
+        packet
+    }
+    fn write(&mut self, frame: &mut [u8], sequence_number: bool) {
+        if frame.len() > 3 {
+            frame[..3].copy_from_slice(&[0x04, 0xff, 0xfe]);
+        }
+        self.counter = self.counter.wrapping_add(1);
+        if sequence_number {
+            if let Some(last) = frame.last_mut() {
+                *last = self.counter;
+            }
+        }
+    }
+}
 
-        // The next two bytes are the length of the data.
-        let (bytes, len) = nom::number::complete::le_u16(bytes)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use tokio::sync::oneshot;
 
-        // The next `len` bytes are the data.
-        let (bytes, data) = nom::bytes::complete::take(len)(bytes)?;
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Audio,
+        ChatText(String),
+        LicenceRequired(LicenceType),
+        SendFailed(ProtocolMessage),
+        Reconnect(ReconnectEvent),
+        State(ConnectionState),
+        ServerVersion(VersionInfo),
+        Drain,
+    }
 
-        // Verify the checksum.
-        let expected = crc::crc(&input_bytes[0..((len as usize) + 7)]).to_le_bytes();
+    struct RecordingHandler {
+        events: mpsc::UnboundedSender<Event>,
+        /// How long `on_drain` takes.
+        drain_time: Duration,
+        /// Whether state changes are recorded, as most tests do not care.
+        record_states: bool,
+    }
+    #[async_trait]
+    impl Handler for RecordingHandler {
+        async fn handle_opus_packet(&mut self, _packet: &[u8], _sequence_number: Option<u8>) {
+            let _ = self.events.send(Event::Audio);
+        }
+        async fn handle_chat_text(&mut self, text: &str) {
+            let _ = self.events.send(Event::ChatText(String::from(text)));
+        }
+        async fn on_licence_required(&mut self, licence_type: LicenceType) {
+            let _ = self.events.send(Event::LicenceRequired(licence_type));
+        }
+        async fn on_send_failed(&mut self, message: &ProtocolMessage) {
+            let _ = self.events.send(Event::SendFailed(message.clone()));
+        }
+        async fn on_reconnect_event(&mut self, event: &ReconnectEvent) {
+            let _ = self.events.send(Event::Reconnect(event.clone()));
+        }
+        async fn on_server_version(&mut self, version: &VersionInfo) {
+            let _ = self.events.send(Event::ServerVersion(version.clone()));
+        }
+        async fn on_state_change(&mut self, state: ConnectionState) {
+            if self.record_states {
+                let _ = self.events.send(Event::State(state));
+            }
+        }
+        async fn on_drain(&mut self) {
+            let _ = self.events.send(Event::Drain);
+            sleep(self.drain_time).await;
+        }
+    }
 
-        // Finally, two more bytes for the checksum.
-        let (bytes, _) = nom::bytes::complete::tag(expected)(bytes)?;
+    /// A fake server, and a client connected to it that records what its handler sees.
+    async fn connect(
+        builder: JamulusClientBuilder,
+    ) -> (
+        UdpSocket,
+        JamulusClient<RecordingHandler>,
+        mpsc::UnboundedReceiver<Event>,
+    ) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server.local_addr().unwrap()).await.unwrap();
+        let (sender, events) = mpsc::unbounded_channel();
+        let handler = RecordingHandler {
+            events: sender,
+            drain_time: Duration::ZERO,
+            record_states: false,
+        };
+        let client = builder.build(socket, handler);
+        (server, client, events)
+    }
 
-        // Return the parsed message.
-        Ok((bytes, Message { id, counter, data }))
+    /// Waits for a protocol message from the client, skipping audio.
+    async fn receive_message(server: &UdpSocket) -> (ProtocolMessage, SocketAddr) {
+        let mut buf = [0; 2048];
+        loop {
+            let (n, source) = server.recv_from(&mut buf).await.unwrap();
+            if let Some((_, message)) = ProtocolMessage::from_datagram(&buf[..n]) {
+                return (message, source);
+            }
+        }
     }
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(9 + self.data.len());
-        bytes.write(&[0x00, 0x00]).unwrap();
-        bytes.write(&self.id.to_le_bytes()).unwrap();
-        bytes.write(&self.counter.to_le_bytes()).unwrap();
-        bytes
-            .write(&(self.data.len() as u16).to_le_bytes())
-            .unwrap();
-        bytes.write(&self.data).unwrap();
-        let crc = crc::crc(&bytes);
-        bytes.write(&crc.to_le_bytes()).unwrap();
-        bytes
+
+    /// Waits for a protocol message from the client, acknowledging it like
+    /// a server would, so that the next one can follow. Skips audio and
+    /// the client's own acknowledgements.
+    async fn receive_acknowledged(server: &UdpSocket) -> ProtocolMessage {
+        let mut buf = [0; 2048];
+        loop {
+            let (n, source) = server.recv_from(&mut buf).await.unwrap();
+            match ProtocolMessage::from_datagram(&buf[..n]) {
+                Some((_, ProtocolMessage::Acknowledgement { .. })) | None => {}
+                Some((_, message)) if message.is_connectionless() => return message,
+                Some((counter, message)) => {
+                    let message_id = message.id();
+                    let ack = ProtocolMessage::Acknowledgement { message_id };
+                    server
+                        .send_to(&ack.to_datagram(counter), source)
+                        .await
+                        .unwrap();
+                    return message;
+                }
+            }
+        }
     }
-}
 
-struct SilentOpusStream {
-    counter: u8,
-}
-impl SilentOpusStream {
-    pub fn new() -> Self {
-        SilentOpusStream { counter: 0 }
+    async fn next_event(events: &mut mpsc::UnboundedReceiver<Event>) -> Event {
+        tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .expect("timed out waiting for an event")
+            .unwrap()
     }
-    pub fn next(&mut self) -> [u8; 332] {
-        let mut packet: [u8; 332] = [0; 332];
-        self.write(&mut packet[..]);
-        self.write(&mut packet[166..]);
-        packet
+
+    /// Waits for the next state change, skipping other events.
+    async fn next_state(events: &mut mpsc::UnboundedReceiver<Event>) -> ConnectionState {
+        loop {
+            if let Event::State(state) = next_event(events).await {
+                return state;
+            }
+        }
     }
-    fn write(&mut self, slice: &mut [u8]) {
-        slice[0] = 0x04;
-        slice[1] = 0xff;
-        slice[2] = 0xfe;
-        self.counter = self.counter.wrapping_add(1);
-        slice[165] = self.counter;
+
+    #[tokio::test]
+    async fn follows_connection_state() {
+        let (server, mut client, mut events) = connect(JamulusClientBuilder::new("test")).await;
+        client.handler.record_states = true;
+        let mut handle = client.handle();
+        let policy = ReconnectPolicy {
+            timeout: Duration::from_secs(10),
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(100),
+            max_attempts: None,
+        };
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move { client.run_with_reconnect(stopped, policy).await });
+
+        let (_, client_addr) = server.recv_from(&mut [0; 2048]).await.unwrap();
+        assert_eq!(handle.state(), ConnectionState::Connecting);
+
+        let client_id = ProtocolMessage::ClientId { channel_id: 0 }.to_datagram(0);
+        server.send_to(&client_id, client_addr).await.unwrap();
+        assert_eq!(handle.state_changed().await, Ok(ConnectionState::Connected));
+        assert_eq!(next_state(&mut events).await, ConnectionState::Connected);
+
+        let server_full = ProtocolMessage::ClmServerFull.to_datagram(0);
+        server.send_to(&server_full, client_addr).await.unwrap();
+        assert_eq!(
+            handle.state_changed().await,
+            Ok(ConnectionState::ServerFull)
+        );
+        assert_eq!(next_state(&mut events).await, ConnectionState::ServerFull);
+
+        // Trying again: this time, audio shows that the server took us in.
+        assert_eq!(
+            handle.state_changed().await,
+            Ok(ConnectionState::Connecting)
+        );
+        assert_eq!(next_state(&mut events).await, ConnectionState::Connecting);
+        server.send_to(&[0x04; 332], client_addr).await.unwrap();
+        assert_eq!(handle.state_changed().await, Ok(ConnectionState::Connected));
+        assert_eq!(next_state(&mut events).await, ConnectionState::Connected);
+
+        stop.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+        assert_eq!(
+            next_state(&mut events).await,
+            ConnectionState::Disconnecting
+        );
+        assert_eq!(next_state(&mut events).await, ConnectionState::Disconnected);
+        assert_eq!(handle.state(), ConnectionState::Disconnected);
     }
-}
 
-#[derive(Debug)]
-struct ClientInfo {
-    channel_id: u8,
-    country_id: u16,
-    instrument_id: u32,
-    skill_level: u8,
-    name: String,
-    city: String,
-}
-impl ClientInfo {
-    fn parse_client<'a>(bytes: &'a [u8]) -> IResult<&'a [u8], ClientInfo> {
-        let (bytes, channel_id) = nom::number::complete::le_u8(bytes)?;
-        let (bytes, country_id) = nom::number::complete::le_u16(bytes)?;
-        let (bytes, instrument_id) = nom::number::complete::le_u32(bytes)?;
-        let (bytes, skill_level) = nom::number::complete::le_u8(bytes)?;
-        let (bytes, _ip) = nom::number::complete::le_u32(bytes)?;
-        let (bytes, name_len) = nom::number::complete::le_u16(bytes)?;
-        let (bytes, name) = nom::bytes::complete::take(name_len)(bytes)?;
-        let name_str = match std::str::from_utf8(name) {
-            Ok(s) => s,
-            Err(_) => {
-                return Err(nom::Err::Failure(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Satisfy,
-                )))
+    #[tokio::test]
+    async fn exchanges_versions_with_server() {
+        let builder = JamulusClientBuilder::new("test").version("3.8.2-test");
+        let (server, mut client, mut events) = connect(builder).await;
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move {
+            let result = client.run(stopped).await;
+            (client, result)
+        });
+
+        let (_, client_addr) = server.recv_from(&mut [0; 2048]).await.unwrap();
+        let client_id = ProtocolMessage::ClientId { channel_id: 0 }.to_datagram(0);
+        server.send_to(&client_id, client_addr).await.unwrap();
+        assert_eq!(
+            receive_acknowledged(&server).await,
+            ProtocolMessage::VersionAndOs {
+                os_type: OsType::current(),
+                version: String::from("3.8.2-test"),
             }
+        );
+
+        let version = VersionInfo {
+            os_type: OsType::Linux,
+            version: String::from("3.9.0"),
+        };
+        let message = ProtocolMessage::VersionAndOs {
+            os_type: version.os_type,
+            version: version.version.clone(),
+        };
+        server
+            .send_to(&message.to_datagram(1), client_addr)
+            .await
+            .unwrap();
+        assert_eq!(next_event(&mut events).await, Event::ServerVersion(version));
+
+        // The answer to `CLM_REQ_VERSION_AND_OS`.
+        let version = VersionInfo {
+            os_type: OsType::Windows,
+            version: String::from("3.10.0"),
         };
-        let (bytes, city_len) = nom::number::complete::le_u16(bytes)?;
-        let (bytes, city) = nom::bytes::complete::take(city_len)(bytes)?;
-        let city_str = match std::str::from_utf8(city) {
-            Ok(s) => s,
-            Err(_) => {
-                return Err(nom::Err::Failure(nom::error::make_error(
-                    bytes,
-                    nom::error::ErrorKind::Satisfy,
-                )))
+        let message = ProtocolMessage::ClmVersionAndOs {
+            os_type: version.os_type,
+            version: version.version.clone(),
+        };
+        server
+            .send_to(&message.to_datagram(0), client_addr)
+            .await
+            .unwrap();
+        assert_eq!(
+            next_event(&mut events).await,
+            Event::ServerVersion(version.clone())
+        );
+
+        stop.send(()).unwrap();
+        let (client, result) = running.await.unwrap();
+        assert!(result.is_ok());
+        assert_eq!(client.server_version(), Some(&version));
+    }
+
+    #[tokio::test]
+    async fn sends_channel_infos() {
+        let builder = JamulusClientBuilder::new("test")
+            .country(Country::Thailand)
+            .instrument(Instrument::GuitarVocal)
+            .skill_level(1)
+            .city("Bangkok");
+        let (server, mut client, _events) = connect(builder).await;
+        let handle = client.handle();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move { client.run(stopped).await });
+        async fn receive_channel_infos(server: &UdpSocket) -> ChannelInfo {
+            loop {
+                if let ProtocolMessage::ChannelInfos(info) = receive_acknowledged(server).await {
+                    return info;
+                }
             }
+        }
+
+        let (_, client_addr) = server.recv_from(&mut [0; 2048]).await.unwrap();
+        let request = ProtocolMessage::RequestChannelInfos.to_datagram(0);
+        server.send_to(&request, client_addr).await.unwrap();
+        let info = ChannelInfo {
+            country: Country::Thailand,
+            instrument: Instrument::GuitarVocal,
+            skill_level: 1,
+            name: String::from("test"),
+            city: String::from("Bangkok"),
         };
-        Ok((
-            bytes,
-            ClientInfo {
-                channel_id,
-                country_id,
-                instrument_id,
-                skill_level,
-                name: String::from(name_str),
-                city: String::from(city_str),
-            },
-        ))
-    }
-    fn parse_all<'a>(mut bytes: &'a [u8]) -> Result<Vec<ClientInfo>, Box<dyn Error + 'a>> {
-        let mut clients = Vec::new();
+        assert_eq!(receive_channel_infos(&server).await, info);
+
+        // Changes mid-session are sent right away.
+        handle.set_name("renamed").await.unwrap();
+        let renamed = ChannelInfo {
+            name: String::from("renamed"),
+            ..info
+        };
+        assert_eq!(receive_channel_infos(&server).await, renamed);
+
+        let info = ChannelInfo {
+            country: Country::Germany,
+            instrument: Instrument::DrumSet,
+            skill_level: 3,
+            name: String::from("drummer"),
+            city: String::from("Berlin"),
+        };
+        handle.set_channel_info(info.clone()).await.unwrap();
+        assert_eq!(receive_channel_infos(&server).await, info);
+
+        stop.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn truncates_long_chat_text() {
+        let (server, mut client, _events) = connect(JamulusClientBuilder::new("test")).await;
+        let handle = client.handle();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move { client.run(stopped).await });
+
+        handle.send_chat_text(&"x".repeat(2000)).await.unwrap();
         loop {
-            if bytes.len() <= 0 {
+            if let ProtocolMessage::ChatText { text } = receive_acknowledged(&server).await {
+                assert_eq!(text, "x".repeat(1600));
                 break;
             }
-            let (next_bytes, client) = Self::parse_client(bytes)?;
-            clients.push(client);
-            bytes = next_bytes;
         }
-        Ok(clients)
+
+        stop.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn holds_back_audio_until_licence_is_accepted() {
+        let (server, mut client, mut events) = connect(JamulusClientBuilder::new("test")).await;
+        let handle = client.handle();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move { client.run(stopped).await });
+
+        // The client sends audio right away.
+        let (_, client_addr) = server.recv_from(&mut [0; 2048]).await.unwrap();
+        let send = |message: ProtocolMessage, counter| {
+            let datagram = message.to_datagram(counter);
+            let server = &server;
+            async move { server.send_to(&datagram, client_addr).await.unwrap() }
+        };
+        let audio = [0x04; 332];
+
+        let licence_type = LicenceType::CreativeCommons;
+        send(ProtocolMessage::LicenceRequired { licence_type }, 0).await;
+        assert_eq!(
+            next_event(&mut events).await,
+            Event::LicenceRequired(licence_type)
+        );
+
+        // Audio is dropped: the chat message sent after it is the next event.
+        server.send_to(&audio, client_addr).await.unwrap();
+        let text = String::from("after audio");
+        send(ProtocolMessage::ChatText { text: text.clone() }, 1).await;
+        assert_eq!(next_event(&mut events).await, Event::ChatText(text));
+
+        // Commands are carried out in order: once the version request
+        // arrives, the licence has been accepted.
+        handle.accept_licence().await.unwrap();
+        handle.request_server_version().await.unwrap();
+        while receive_message(&server).await.0 != ProtocolMessage::ClmRequestVersionAndOs {}
+        server.send_to(&audio, client_addr).await.unwrap();
+        assert_eq!(next_event(&mut events).await, Event::Audio);
+
+        stop.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_timeout_and_when_server_is_full() {
+        let (server, mut client, mut events) = connect(JamulusClientBuilder::new("test")).await;
+        let handle = client.handle();
+        let policy = ReconnectPolicy {
+            timeout: Duration::from_millis(200),
+            initial_delay: Duration::from_millis(300),
+            max_delay: Duration::from_millis(300),
+            max_attempts: None,
+        };
+        let (stop, stopped) = oneshot::channel::<()>();
+        let running = tokio::spawn(async move { client.run_with_reconnect(stopped, policy).await });
+
+        let (_, client_addr) = server.recv_from(&mut [0; 2048]).await.unwrap();
+        let client_id = ProtocolMessage::ClientId { channel_id: 0 }.to_datagram(0);
+        server.send_to(&client_id, client_addr).await.unwrap();
+
+        // The server goes quiet.
+        assert_eq!(
+            next_event(&mut events).await,
+            Event::Reconnect(ReconnectEvent::SessionEnded(SessionEnd::TimedOut))
+        );
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::Reconnect(ReconnectEvent::Reconnecting { attempt: 1, .. })
+        ));
+
+        // A chat message sent while waiting cannot reach the old session.
+        handle.send_chat_text("hello?").await.unwrap();
+        server.send_to(&client_id, client_addr).await.unwrap();
+        let mut failed = vec![];
+        loop {
+            match next_event(&mut events).await {
+                Event::SendFailed(message) => failed.push(message),
+                Event::Reconnect(ReconnectEvent::Reconnected) => break,
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        assert!(failed.contains(&ProtocolMessage::ChatText {
+            text: String::from("hello?")
+        }));
+
+        // After a successful reconnection, the backoff starts over.
+        let server_full = ProtocolMessage::ClmServerFull.to_datagram(0);
+        server.send_to(&server_full, client_addr).await.unwrap();
+        assert_eq!(
+            next_event(&mut events).await,
+            Event::Reconnect(ReconnectEvent::SessionEnded(SessionEnd::ServerFull))
+        );
+        assert!(matches!(
+            next_event(&mut events).await,
+            Event::Reconnect(ReconnectEvent::Reconnecting { attempt: 1, .. })
+        ));
+
+        stop.send(()).unwrap();
+        assert!(running.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn says_goodbye_before_draining_within_deadline() {
+        let builder =
+            JamulusClientBuilder::new("test").disconnect_timeout(Duration::from_millis(300));
+        let (server, mut client, mut events) = connect(builder).await;
+        client.handler.drain_time = Duration::from_secs(10);
+
+        let started = Instant::now();
+        assert!(client.run(std::future::ready(())).await.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(client.state(), ConnectionState::Disconnected);
+        assert_eq!(next_event(&mut events).await, Event::Drain);
+
+        let mut disconnections = 0;
+        let mut buf = [0; 2048];
+        while let Ok(Ok((n, _))) =
+            tokio::time::timeout(Duration::from_millis(100), server.recv_from(&mut buf)).await
+        {
+            if let Some((_, ProtocolMessage::ClmDisconnection)) =
+                ProtocolMessage::from_datagram(&buf[..n])
+            {
+                disconnections += 1;
+            }
+        }
+        assert_eq!(disconnections, DISCONNECTION_ATTEMPTS);
     }
 }
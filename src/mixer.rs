@@ -0,0 +1,272 @@
+//! Our own mix of the other clients.
+//!
+//! Settings are remembered by channel ID or by client name and checked
+//! against every `CONN_CLIENTS_LIST`, because the server resets them when a
+//! client (re)joins. Only values that differ from what the server has are
+//! sent, as each message is a round trip in the reliable queue.
+
+use crate::protocol::{ClientInfo, ProtocolMessage};
+use std::collections::HashMap;
+
+/// Full gain, also the server's default.
+const MAX_GAIN: u16 = 0x8000;
+
+/// Pan to the right.
+const MAX_PAN: u16 = 0x8000;
+
+/// Pan in the middle, between 0 (left) and `MAX_PAN` (right), also the server's default.
+const CENTER_PAN: u16 = 0x4000;
+
+/// Identifies a channel in the mix.
+///
+/// Channel IDs are reused by the server after a client leaves, while names
+/// follow a person across reconnections. Settings for a channel ID take
+/// precedence over settings for a name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ChannelKey {
+    Id(u8),
+    Name(String),
+}
+impl From<u8> for ChannelKey {
+    fn from(channel_id: u8) -> Self {
+        ChannelKey::Id(channel_id)
+    }
+}
+impl From<&str> for ChannelKey {
+    fn from(name: &str) -> Self {
+        ChannelKey::Name(String::from(name))
+    }
+}
+impl From<String> for ChannelKey {
+    fn from(name: String) -> Self {
+        ChannelKey::Name(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChannelSettings {
+    gain: u16,
+    pan: u16,
+    muted: bool,
+    solo: bool,
+}
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            gain: MAX_GAIN,
+            pan: CENTER_PAN,
+            muted: false,
+            solo: false,
+        }
+    }
+}
+
+/// What the server has for a channel, as far as we know.
+struct SentSettings {
+    /// The client on the channel when we sent the settings: the server
+    /// resets them if another client takes over the channel.
+    name: String,
+    gain: u16,
+    pan: u16,
+}
+impl SentSettings {
+    fn server_default(name: &str) -> Self {
+        SentSettings {
+            name: String::from(name),
+            gain: MAX_GAIN,
+            pan: CENTER_PAN,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Mixer {
+    by_id: HashMap<u8, ChannelSettings>,
+    by_name: HashMap<String, ChannelSettings>,
+    exclude_own_channel: bool,
+    sent: HashMap<u8, SentSettings>,
+}
+impl Mixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_gain(&mut self, key: ChannelKey, gain: u16) {
+        self.entry(key).gain = gain.min(MAX_GAIN);
+    }
+    pub fn set_pan(&mut self, key: ChannelKey, pan: u16) {
+        self.entry(key).pan = pan.min(MAX_PAN);
+    }
+    pub fn set_muted(&mut self, key: ChannelKey, muted: bool) {
+        self.entry(key).muted = muted;
+    }
+    /// While any client in the list is soloed, all other clients are silenced.
+    pub fn set_solo(&mut self, key: ChannelKey, solo: bool) {
+        self.entry(key).solo = solo;
+    }
+    /// Silences our own channel, so that the mix only contains the others.
+    pub fn set_exclude_own_channel(&mut self, exclude: bool) {
+        self.exclude_own_channel = exclude;
+    }
+
+    /// Returns the `CHANNEL_GAIN` and `CHANNEL_PAN` messages that apply
+    /// the settings to the given clients, leaving out values the server
+    /// already has.
+    pub fn messages(
+        &mut self,
+        clients: &[ClientInfo],
+        own_channel_id: Option<u8>,
+    ) -> Vec<ProtocolMessage> {
+        // Channels that were left, or taken over by another client, are
+        // back to the server's defaults.
+        self.sent.retain(|channel_id, sent| {
+            clients
+                .iter()
+                .any(|client| client.channel_id == *channel_id && client.name == sent.name)
+        });
+
+        let any_solo = clients.iter().any(|client| self.settings(client).solo);
+        let mut messages = Vec::new();
+        for client in clients {
+            let settings = self.settings(client);
+            let silenced = settings.muted
+                || (any_solo && !settings.solo)
+                || (self.exclude_own_channel && own_channel_id == Some(client.channel_id));
+            let gain = if silenced { 0 } else { settings.gain };
+            let channel_id = client.channel_id;
+            let sent = self
+                .sent
+                .entry(channel_id)
+                .or_insert_with(|| SentSettings::server_default(&client.name));
+            if sent.gain != gain {
+                sent.gain = gain;
+                messages.push(ProtocolMessage::ChannelGain { channel_id, gain });
+            }
+            if sent.pan != settings.pan {
+                sent.pan = settings.pan;
+                messages.push(ProtocolMessage::ChannelPan {
+                    channel_id,
+                    pan: settings.pan,
+                });
+            }
+        }
+        messages
+    }
+
+    /// Forgets what was sent, e.g. when starting a new session with the
+    /// server, which then has its defaults for every channel.
+    pub fn forget_sent(&mut self) {
+        self.sent.clear();
+    }
+
+    fn settings(&self, client: &ClientInfo) -> ChannelSettings {
+        self.by_id
+            .get(&client.channel_id)
+            .or_else(|| self.by_name.get(&client.name))
+            .copied()
+            .unwrap_or_default()
+    }
+    fn entry(&mut self, key: ChannelKey) -> &mut ChannelSettings {
+        match key {
+            ChannelKey::Id(channel_id) => self.by_id.entry(channel_id).or_default(),
+            ChannelKey::Name(name) => self.by_name.entry(name).or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gains(messages: &[ProtocolMessage]) -> Vec<(u8, u16)> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                ProtocolMessage::ChannelGain { channel_id, gain } => Some((*channel_id, *gain)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn applies_settings_by_id_and_name() {
+        let mut mixer = Mixer::new();
+        mixer.set_gain(ChannelKey::from("bass"), 0x2000);
+        mixer.set_gain(ChannelKey::from(2), 0x1000);
+        mixer.set_pan(ChannelKey::from("bass"), 0);
+
+        let clients = [
+            ClientInfo::named(0, "drums"),
+            ClientInfo::named(1, "bass"),
+            ClientInfo::named(2, "bass"),
+        ];
+        let messages = mixer.messages(&clients, None);
+        assert_eq!(gains(&messages), vec![(1, 0x2000), (2, 0x1000)]);
+        assert!(messages.contains(&ProtocolMessage::ChannelPan {
+            channel_id: 1,
+            pan: 0
+        }));
+        // Untouched channels keep the server's defaults.
+        assert!(!messages
+            .iter()
+            .any(|message| matches!(message, ProtocolMessage::ChannelPan { channel_id: 0, .. })));
+
+        // The name follows the client when it rejoins on another channel.
+        let messages = mixer.messages(&[ClientInfo::named(5, "bass")], None);
+        assert_eq!(gains(&messages), vec![(5, 0x2000)]);
+    }
+
+    #[test]
+    fn mutes_solos_and_excludes_own_channel() {
+        let mut mixer = Mixer::new();
+        let clients = [
+            ClientInfo::named(0, "me"),
+            ClientInfo::named(1, "guitar"),
+            ClientInfo::named(2, "vocal"),
+        ];
+
+        mixer.set_exclude_own_channel(true);
+        mixer.set_muted(ChannelKey::from("vocal"), true);
+        assert_eq!(
+            gains(&mixer.messages(&clients, Some(0))),
+            vec![(0, 0), (2, 0)]
+        );
+
+        mixer.set_muted(ChannelKey::from("vocal"), false);
+        mixer.set_solo(ChannelKey::from("vocal"), true);
+        assert_eq!(
+            gains(&mixer.messages(&clients, Some(0))),
+            vec![(1, 0), (2, 0x8000)]
+        );
+    }
+
+    #[test]
+    fn only_sends_changes() {
+        let mut mixer = Mixer::new();
+        mixer.set_gain(ChannelKey::from(1), 0x1000);
+        mixer.set_pan(ChannelKey::from(1), 0xffff);
+        let clients = [ClientInfo::named(0, "drums"), ClientInfo::named(1, "bass")];
+        assert_eq!(
+            mixer.messages(&clients, None),
+            vec![
+                ProtocolMessage::ChannelGain {
+                    channel_id: 1,
+                    gain: 0x1000
+                },
+                ProtocolMessage::ChannelPan {
+                    channel_id: 1,
+                    pan: MAX_PAN
+                },
+            ]
+        );
+        assert_eq!(mixer.messages(&clients, None), vec![]);
+
+        // Someone else took over channel 1: the server reset it.
+        let clients = [ClientInfo::named(0, "drums"), ClientInfo::named(1, "keys")];
+        assert_eq!(gains(&mixer.messages(&clients, None)), vec![(1, 0x1000)]);
+        assert_eq!(mixer.messages(&clients, None), vec![]);
+
+        mixer.forget_sent();
+        assert_eq!(gains(&mixer.messages(&clients, None)), vec![(1, 0x1000)]);
+    }
+}
@@ -0,0 +1,1167 @@
+//! Typed representation of the Jamulus protocol messages.
+//!
+//! Every protocol datagram is framed by [`Message`] (header, payload, CRC).
+//! [`ProtocolMessage`] gives meaning to the payload of each message ID, with
+//! `encode` and `decode` being exact inverses of each other.
+
+use crate::crc;
+use crate::tables::{Country, Instrument};
+use nom::IResult;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+const ACKN: u16 = 1;
+const JITT_BUF_SIZE: u16 = 10;
+const REQ_JITT_BUF_SIZE: u16 = 11;
+const CHANNEL_GAIN: u16 = 13;
+const REQ_CONN_CLIENTS_LIST: u16 = 16;
+const CHAT_TEXT: u16 = 18;
+const NETW_TRANSPORT_PROPS: u16 = 20;
+const REQ_NETW_TRANSPORT_PROPS: u16 = 21;
+const REQ_CHANNEL_INFOS: u16 = 23;
+const CONN_CLIENTS_LIST: u16 = 24;
+const CHANNEL_INFOS: u16 = 25;
+const LICENCE_REQUIRED: u16 = 27;
+const REQ_CHANNEL_LEVEL_LIST: u16 = 28;
+const VERSION_AND_OS: u16 = 29;
+const CHANNEL_PAN: u16 = 30;
+const MUTE_STATE_CHANGED: u16 = 31;
+const CLIENT_ID: u16 = 32;
+const RECORDER_STATE: u16 = 33;
+const REQ_SPLIT_MESS_SUPPORT: u16 = 34;
+const SPLIT_MESS_SUPPORTED: u16 = 35;
+const CLM_PING_MS: u16 = 1001;
+const CLM_PING_MS_WITHNUMCLIENTS: u16 = 1002;
+const CLM_SERVER_FULL: u16 = 1003;
+const CLM_SERVER_LIST: u16 = 1006;
+const CLM_REQ_SERVER_LIST: u16 = 1007;
+const CLM_SEND_EMPTY_MESSAGE: u16 = 1008;
+const CLM_EMPTY_MESSAGE: u16 = 1009;
+const CLM_DISCONNECTION: u16 = 1010;
+const CLM_VERSION_AND_OS: u16 = 1011;
+const CLM_REQ_VERSION_AND_OS: u16 = 1012;
+const CLM_CONN_CLIENTS_LIST: u16 = 1013;
+const CLM_REQ_CONN_CLIENTS_LIST: u16 = 1014;
+const CLM_CHANNEL_LEVEL_LIST: u16 = 1015;
+const CLM_RED_SERVER_LIST: u16 = 1018;
+const CLM_REQ_RED_SERVER_LIST: u16 = 1019;
+const SPECIAL_SPLIT_MESSAGE: u16 = 2001;
+
+/// A decoded Jamulus protocol message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolMessage {
+    /// `ACKN`: acknowledges the message with the given ID.
+    /// The acknowledged counter is carried in the message header.
+    Acknowledgement { message_id: u16 },
+    /// `JITT_BUF_SIZE`: jitter buffer size in blocks.
+    JitterBufferSize { blocks: u16 },
+    /// `REQ_JITT_BUF_SIZE`
+    RequestJitterBufferSize,
+    /// `CHANNEL_GAIN`: gain from 0 to 0x8000 for the given channel.
+    ChannelGain { channel_id: u8, gain: u16 },
+    /// `REQ_CONN_CLIENTS_LIST`
+    RequestConnectedClientsList,
+    /// `CHAT_TEXT`
+    ChatText { text: String },
+    /// `NETW_TRANSPORT_PROPS`
+    NetworkTransportProps(NetworkTransportProps),
+    /// `REQ_NETW_TRANSPORT_PROPS`
+    RequestNetworkTransportProps,
+    /// `REQ_CHANNEL_INFOS`
+    RequestChannelInfos,
+    /// `CONN_CLIENTS_LIST`
+    ConnectedClientsList { clients: Vec<ClientInfo> },
+    /// `CHANNEL_INFOS`: our own channel information.
+    ChannelInfos(ChannelInfo),
+    /// `LICENCE_REQUIRED`: the licence must be accepted before taking part.
+    LicenceRequired { licence_type: LicenceType },
+    /// `REQ_CHANNEL_LEVEL_LIST`: enables or disables channel level updates.
+    RequestChannelLevelList { enabled: bool },
+    /// `VERSION_AND_OS`
+    VersionAndOs { os_type: OsType, version: String },
+    /// `CHANNEL_PAN`: pan from 0 (left) to 0x8000 (right) for the given channel.
+    ChannelPan { channel_id: u8, pan: u16 },
+    /// `MUTE_STATE_CHANGED`: the given channel has (un)muted us.
+    MuteStateChanged { channel_id: u8, muted: bool },
+    /// `CLIENT_ID`: our own channel ID on the server.
+    ClientId { channel_id: u8 },
+    /// `RECORDER_STATE`
+    RecorderState { state: RecorderState },
+    /// `REQ_SPLIT_MESS_SUPPORT`
+    RequestSplitMessageSupport,
+    /// `SPLIT_MESS_SUPPORTED`
+    SplitMessageSupported,
+    /// `SPECIAL_SPLIT_MESSAGE`: one part of a message that was too large for a single datagram.
+    SplitMessage {
+        message_id: u16,
+        num_parts: u8,
+        part_index: u8,
+        data: Vec<u8>,
+    },
+    /// `CLM_PING_MS`: echoed back by the server with the same time.
+    ClmPingMs { time_ms: u32 },
+    /// `CLM_PING_MS_WITHNUMCLIENTS`
+    ClmPingMsWithNumClients { time_ms: u32, num_clients: u8 },
+    /// `CLM_SERVER_FULL`
+    ClmServerFull,
+    /// `CLM_SERVER_LIST`: the servers registered with a directory.
+    ClmServerList { servers: Vec<ServerInfo> },
+    /// `CLM_REQ_SERVER_LIST`
+    ClmRequestServerList,
+    /// `CLM_SEND_EMPTY_MESSAGE`: asks a server to send `CLM_EMPTY_MESSAGE`
+    /// to the given address, opening a path through its NAT.
+    ClmSendEmptyMessage { address: SocketAddr },
+    /// `CLM_EMPTY_MESSAGE`
+    ClmEmptyMessage,
+    /// `CLM_DISCONNECTION`
+    ClmDisconnection,
+    /// `CLM_VERSION_AND_OS`
+    ClmVersionAndOs { os_type: OsType, version: String },
+    /// `CLM_REQ_VERSION_AND_OS`
+    ClmRequestVersionAndOs,
+    /// `CLM_CONN_CLIENTS_LIST`
+    ClmConnectedClientsList { clients: Vec<ClientInfo> },
+    /// `CLM_REQ_CONN_CLIENTS_LIST`
+    ClmRequestConnectedClientsList,
+    /// `CLM_CHANNEL_LEVEL_LIST`: one level from 0 to 15 per client,
+    /// in the order of the last `CONN_CLIENTS_LIST`. With an odd number of
+    /// clients, the payload ends with one padding nibble (0xF), which is
+    /// decoded as an extra level: only the client list tells them apart.
+    ClmChannelLevelList { levels: Vec<u8> },
+    /// `CLM_RED_SERVER_LIST`: like `CLM_SERVER_LIST` but with only addresses and names.
+    ClmReducedServerList { servers: Vec<ServerInfo> },
+    /// `CLM_REQ_RED_SERVER_LIST`
+    ClmRequestReducedServerList,
+    /// Any message ID not known to this library, kept verbatim.
+    Unknown { id: u16, data: Vec<u8> },
+}
+
+impl ProtocolMessage {
+    /// Returns the Jamulus message ID.
+    pub fn id(&self) -> u16 {
+        match self {
+            ProtocolMessage::Acknowledgement { .. } => ACKN,
+            ProtocolMessage::JitterBufferSize { .. } => JITT_BUF_SIZE,
+            ProtocolMessage::RequestJitterBufferSize => REQ_JITT_BUF_SIZE,
+            ProtocolMessage::ChannelGain { .. } => CHANNEL_GAIN,
+            ProtocolMessage::RequestConnectedClientsList => REQ_CONN_CLIENTS_LIST,
+            ProtocolMessage::ChatText { .. } => CHAT_TEXT,
+            ProtocolMessage::NetworkTransportProps(_) => NETW_TRANSPORT_PROPS,
+            ProtocolMessage::RequestNetworkTransportProps => REQ_NETW_TRANSPORT_PROPS,
+            ProtocolMessage::RequestChannelInfos => REQ_CHANNEL_INFOS,
+            ProtocolMessage::ConnectedClientsList { .. } => CONN_CLIENTS_LIST,
+            ProtocolMessage::ChannelInfos(_) => CHANNEL_INFOS,
+            ProtocolMessage::LicenceRequired { .. } => LICENCE_REQUIRED,
+            ProtocolMessage::RequestChannelLevelList { .. } => REQ_CHANNEL_LEVEL_LIST,
+            ProtocolMessage::VersionAndOs { .. } => VERSION_AND_OS,
+            ProtocolMessage::ChannelPan { .. } => CHANNEL_PAN,
+            ProtocolMessage::MuteStateChanged { .. } => MUTE_STATE_CHANGED,
+            ProtocolMessage::ClientId { .. } => CLIENT_ID,
+            ProtocolMessage::RecorderState { .. } => RECORDER_STATE,
+            ProtocolMessage::RequestSplitMessageSupport => REQ_SPLIT_MESS_SUPPORT,
+            ProtocolMessage::SplitMessageSupported => SPLIT_MESS_SUPPORTED,
+            ProtocolMessage::SplitMessage { .. } => SPECIAL_SPLIT_MESSAGE,
+            ProtocolMessage::ClmPingMs { .. } => CLM_PING_MS,
+            ProtocolMessage::ClmPingMsWithNumClients { .. } => CLM_PING_MS_WITHNUMCLIENTS,
+            ProtocolMessage::ClmServerFull => CLM_SERVER_FULL,
+            ProtocolMessage::ClmServerList { .. } => CLM_SERVER_LIST,
+            ProtocolMessage::ClmRequestServerList => CLM_REQ_SERVER_LIST,
+            ProtocolMessage::ClmSendEmptyMessage { .. } => CLM_SEND_EMPTY_MESSAGE,
+            ProtocolMessage::ClmEmptyMessage => CLM_EMPTY_MESSAGE,
+            ProtocolMessage::ClmDisconnection => CLM_DISCONNECTION,
+            ProtocolMessage::ClmVersionAndOs { .. } => CLM_VERSION_AND_OS,
+            ProtocolMessage::ClmRequestVersionAndOs => CLM_REQ_VERSION_AND_OS,
+            ProtocolMessage::ClmConnectedClientsList { .. } => CLM_CONN_CLIENTS_LIST,
+            ProtocolMessage::ClmRequestConnectedClientsList => CLM_REQ_CONN_CLIENTS_LIST,
+            ProtocolMessage::ClmChannelLevelList { .. } => CLM_CHANNEL_LEVEL_LIST,
+            ProtocolMessage::ClmReducedServerList { .. } => CLM_RED_SERVER_LIST,
+            ProtocolMessage::ClmRequestReducedServerList => CLM_REQ_RED_SERVER_LIST,
+            ProtocolMessage::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Returns true for connectionless (`CLM_*`) messages, which are
+    /// neither acknowledged nor counted.
+    pub fn is_connectionless(&self) -> bool {
+        is_connectionless_id(self.id())
+    }
+
+    /// Encodes the message payload (without framing).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            ProtocolMessage::Acknowledgement { message_id } => {
+                bytes.extend_from_slice(&message_id.to_le_bytes());
+            }
+            ProtocolMessage::JitterBufferSize { blocks } => {
+                bytes.extend_from_slice(&blocks.to_le_bytes());
+            }
+            ProtocolMessage::ChannelGain { channel_id, gain } => {
+                bytes.push(*channel_id);
+                bytes.extend_from_slice(&gain.to_le_bytes());
+            }
+            ProtocolMessage::ChatText { text } => {
+                write_string(&mut bytes, text);
+            }
+            ProtocolMessage::NetworkTransportProps(props) => {
+                props.write(&mut bytes);
+            }
+            ProtocolMessage::ConnectedClientsList { clients }
+            | ProtocolMessage::ClmConnectedClientsList { clients } => {
+                for client in clients {
+                    client.write(&mut bytes);
+                }
+            }
+            ProtocolMessage::ChannelInfos(info) => {
+                info.write(&mut bytes);
+            }
+            ProtocolMessage::LicenceRequired { licence_type } => {
+                bytes.push(licence_type.id());
+            }
+            ProtocolMessage::RequestChannelLevelList { enabled } => {
+                bytes.push(*enabled as u8);
+            }
+            ProtocolMessage::VersionAndOs { os_type, version }
+            | ProtocolMessage::ClmVersionAndOs { os_type, version } => {
+                bytes.push(os_type.id());
+                write_string(&mut bytes, version);
+            }
+            ProtocolMessage::ChannelPan { channel_id, pan } => {
+                bytes.push(*channel_id);
+                bytes.extend_from_slice(&pan.to_le_bytes());
+            }
+            ProtocolMessage::MuteStateChanged { channel_id, muted } => {
+                bytes.push(*channel_id);
+                bytes.push(*muted as u8);
+            }
+            ProtocolMessage::ClientId { channel_id } => {
+                bytes.push(*channel_id);
+            }
+            ProtocolMessage::RecorderState { state } => {
+                bytes.push(state.id());
+            }
+            ProtocolMessage::SplitMessage {
+                message_id,
+                num_parts,
+                part_index,
+                data,
+            } => {
+                bytes.extend_from_slice(&message_id.to_le_bytes());
+                bytes.push(*num_parts);
+                bytes.push(*part_index);
+                bytes.extend_from_slice(data);
+            }
+            ProtocolMessage::ClmPingMs { time_ms } => {
+                bytes.extend_from_slice(&time_ms.to_le_bytes());
+            }
+            ProtocolMessage::ClmPingMsWithNumClients {
+                time_ms,
+                num_clients,
+            } => {
+                bytes.extend_from_slice(&time_ms.to_le_bytes());
+                bytes.push(*num_clients);
+            }
+            ProtocolMessage::ClmChannelLevelList { levels } => {
+                // Two levels per byte, low nibble first, padded with 0xF.
+                for pair in levels.chunks(2) {
+                    let low = pair[0] & 0x0f;
+                    let high = pair.get(1).map_or(0x0f, |level| level & 0x0f);
+                    bytes.push(low | (high << 4));
+                }
+            }
+            ProtocolMessage::ClmServerList { servers } => {
+                for server in servers {
+                    server.write(&mut bytes);
+                }
+            }
+            ProtocolMessage::ClmReducedServerList { servers } => {
+                for server in servers {
+                    server.write_reduced(&mut bytes);
+                }
+            }
+            ProtocolMessage::ClmSendEmptyMessage { address } => {
+                write_address(&mut bytes, address);
+            }
+            ProtocolMessage::Unknown { data, .. } => {
+                bytes.extend_from_slice(data);
+            }
+            ProtocolMessage::RequestJitterBufferSize
+            | ProtocolMessage::RequestConnectedClientsList
+            | ProtocolMessage::RequestNetworkTransportProps
+            | ProtocolMessage::RequestChannelInfos
+            | ProtocolMessage::RequestSplitMessageSupport
+            | ProtocolMessage::SplitMessageSupported
+            | ProtocolMessage::ClmServerFull
+            | ProtocolMessage::ClmRequestServerList
+            | ProtocolMessage::ClmEmptyMessage
+            | ProtocolMessage::ClmDisconnection
+            | ProtocolMessage::ClmRequestVersionAndOs
+            | ProtocolMessage::ClmRequestConnectedClientsList
+            | ProtocolMessage::ClmRequestReducedServerList => {}
+        }
+        bytes
+    }
+
+    /// Decodes the payload of a message with the given ID.
+    pub fn decode(id: u16, data: &[u8]) -> Result<ProtocolMessage, DecodeError> {
+        Self::parse(id, data)
+            .map(|(_, message)| message)
+            .map_err(|_| DecodeError { id })
+    }
+
+    fn parse(id: u16, bytes: &[u8]) -> IResult<&[u8], ProtocolMessage> {
+        use nom::number::complete::{le_u16, le_u32, le_u8};
+        match id {
+            ACKN => {
+                let (bytes, message_id) = le_u16(bytes)?;
+                Ok((bytes, ProtocolMessage::Acknowledgement { message_id }))
+            }
+            JITT_BUF_SIZE => {
+                let (bytes, blocks) = le_u16(bytes)?;
+                Ok((bytes, ProtocolMessage::JitterBufferSize { blocks }))
+            }
+            REQ_JITT_BUF_SIZE => Ok((bytes, ProtocolMessage::RequestJitterBufferSize)),
+            CHANNEL_GAIN => {
+                let (bytes, channel_id) = le_u8(bytes)?;
+                let (bytes, gain) = le_u16(bytes)?;
+                Ok((bytes, ProtocolMessage::ChannelGain { channel_id, gain }))
+            }
+            REQ_CONN_CLIENTS_LIST => Ok((bytes, ProtocolMessage::RequestConnectedClientsList)),
+            CHAT_TEXT => {
+                let (bytes, text) = parse_string(bytes)?;
+                Ok((bytes, ProtocolMessage::ChatText { text }))
+            }
+            NETW_TRANSPORT_PROPS => {
+                let (bytes, props) = NetworkTransportProps::parse(bytes)?;
+                Ok((bytes, ProtocolMessage::NetworkTransportProps(props)))
+            }
+            REQ_NETW_TRANSPORT_PROPS => Ok((bytes, ProtocolMessage::RequestNetworkTransportProps)),
+            REQ_CHANNEL_INFOS => Ok((bytes, ProtocolMessage::RequestChannelInfos)),
+            CONN_CLIENTS_LIST => {
+                let (bytes, clients) = parse_all(ClientInfo::parse)(bytes)?;
+                Ok((bytes, ProtocolMessage::ConnectedClientsList { clients }))
+            }
+            CHANNEL_INFOS => {
+                let (bytes, info) = ChannelInfo::parse(bytes)?;
+                Ok((bytes, ProtocolMessage::ChannelInfos(info)))
+            }
+            LICENCE_REQUIRED => {
+                let (bytes, licence_type) = le_u8(bytes)?;
+                let licence_type = LicenceType::from_id(licence_type);
+                Ok((bytes, ProtocolMessage::LicenceRequired { licence_type }))
+            }
+            REQ_CHANNEL_LEVEL_LIST => {
+                let (bytes, enabled) = le_u8(bytes)?;
+                let enabled = enabled != 0;
+                Ok((bytes, ProtocolMessage::RequestChannelLevelList { enabled }))
+            }
+            VERSION_AND_OS => {
+                let (bytes, (os_type, version)) = parse_version_and_os(bytes)?;
+                Ok((bytes, ProtocolMessage::VersionAndOs { os_type, version }))
+            }
+            CHANNEL_PAN => {
+                let (bytes, channel_id) = le_u8(bytes)?;
+                let (bytes, pan) = le_u16(bytes)?;
+                Ok((bytes, ProtocolMessage::ChannelPan { channel_id, pan }))
+            }
+            MUTE_STATE_CHANGED => {
+                let (bytes, channel_id) = le_u8(bytes)?;
+                let (bytes, muted) = le_u8(bytes)?;
+                let muted = muted != 0;
+                Ok((
+                    bytes,
+                    ProtocolMessage::MuteStateChanged { channel_id, muted },
+                ))
+            }
+            CLIENT_ID => {
+                let (bytes, channel_id) = le_u8(bytes)?;
+                Ok((bytes, ProtocolMessage::ClientId { channel_id }))
+            }
+            RECORDER_STATE => {
+                let (bytes, state) = le_u8(bytes)?;
+                let state = RecorderState::from_id(state);
+                Ok((bytes, ProtocolMessage::RecorderState { state }))
+            }
+            REQ_SPLIT_MESS_SUPPORT => Ok((bytes, ProtocolMessage::RequestSplitMessageSupport)),
+            SPLIT_MESS_SUPPORTED => Ok((bytes, ProtocolMessage::SplitMessageSupported)),
+            SPECIAL_SPLIT_MESSAGE => {
+                let (bytes, message_id) = le_u16(bytes)?;
+                let (bytes, num_parts) = le_u8(bytes)?;
+                let (bytes, part_index) = le_u8(bytes)?;
+                let message = ProtocolMessage::SplitMessage {
+                    message_id,
+                    num_parts,
+                    part_index,
+                    data: bytes.to_vec(),
+                };
+                Ok((&bytes[bytes.len()..], message))
+            }
+            CLM_PING_MS => {
+                let (bytes, time_ms) = le_u32(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmPingMs { time_ms }))
+            }
+            CLM_PING_MS_WITHNUMCLIENTS => {
+                let (bytes, time_ms) = le_u32(bytes)?;
+                let (bytes, num_clients) = le_u8(bytes)?;
+                let message = ProtocolMessage::ClmPingMsWithNumClients {
+                    time_ms,
+                    num_clients,
+                };
+                Ok((bytes, message))
+            }
+            CLM_SERVER_FULL => Ok((bytes, ProtocolMessage::ClmServerFull)),
+            CLM_SERVER_LIST => {
+                let (bytes, servers) = parse_all(ServerInfo::parse)(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmServerList { servers }))
+            }
+            CLM_REQ_SERVER_LIST => Ok((bytes, ProtocolMessage::ClmRequestServerList)),
+            CLM_SEND_EMPTY_MESSAGE => {
+                let (bytes, address) = parse_address(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmSendEmptyMessage { address }))
+            }
+            CLM_EMPTY_MESSAGE => Ok((bytes, ProtocolMessage::ClmEmptyMessage)),
+            CLM_DISCONNECTION => Ok((bytes, ProtocolMessage::ClmDisconnection)),
+            CLM_VERSION_AND_OS => {
+                let (bytes, (os_type, version)) = parse_version_and_os(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmVersionAndOs { os_type, version }))
+            }
+            CLM_REQ_VERSION_AND_OS => Ok((bytes, ProtocolMessage::ClmRequestVersionAndOs)),
+            CLM_CONN_CLIENTS_LIST => {
+                let (bytes, clients) = parse_all(ClientInfo::parse)(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmConnectedClientsList { clients }))
+            }
+            CLM_REQ_CONN_CLIENTS_LIST => {
+                Ok((bytes, ProtocolMessage::ClmRequestConnectedClientsList))
+            }
+            CLM_CHANNEL_LEVEL_LIST => {
+                let mut levels = Vec::with_capacity(bytes.len() * 2);
+                for byte in bytes {
+                    levels.push(byte & 0x0f);
+                    levels.push(byte >> 4);
+                }
+                let message = ProtocolMessage::ClmChannelLevelList { levels };
+                Ok((&bytes[bytes.len()..], message))
+            }
+            CLM_RED_SERVER_LIST => {
+                let (bytes, servers) = parse_all(ServerInfo::parse_reduced)(bytes)?;
+                Ok((bytes, ProtocolMessage::ClmReducedServerList { servers }))
+            }
+            CLM_REQ_RED_SERVER_LIST => Ok((bytes, ProtocolMessage::ClmRequestReducedServerList)),
+            _ => {
+                let message = ProtocolMessage::Unknown {
+                    id,
+                    data: bytes.to_vec(),
+                };
+                Ok((&bytes[bytes.len()..], message))
+            }
+        }
+    }
+
+    /// Encodes the message into a complete datagram with the given counter.
+    /// Connectionless messages always use counter 0.
+    pub fn to_datagram(&self, counter: u8) -> Vec<u8> {
+        let data = self.encode();
+        Message {
+            id: self.id(),
+            counter,
+            data: &data,
+        }
+        .to_bytes()
+    }
+
+    /// Parses a complete datagram, returning its counter and decoded message.
+    pub fn from_datagram(bytes: &[u8]) -> Option<(u8, ProtocolMessage)> {
+        let (_, msg) = Message::parse(bytes).ok()?;
+        let message = ProtocolMessage::decode(msg.id, msg.data).ok()?;
+        Some((msg.counter, message))
+    }
+}
+
+/// The payload of a message could not be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub id: u16,
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unable to decode payload of message {}", self.id)
+    }
+}
+impl std::error::Error for DecodeError {}
+
+/// The operating system of a client or server, as sent in `VERSION_AND_OS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OsType {
+    Windows,
+    MacOs,
+    Linux,
+    Android,
+    Ios,
+    Unix,
+    Unknown(u8),
+}
+impl OsType {
+    pub fn from_id(id: u8) -> OsType {
+        match id {
+            0 => OsType::Windows,
+            1 => OsType::MacOs,
+            2 => OsType::Linux,
+            3 => OsType::Android,
+            4 => OsType::Ios,
+            5 => OsType::Unix,
+            id => OsType::Unknown(id),
+        }
+    }
+    pub fn id(self) -> u8 {
+        match self {
+            OsType::Windows => 0,
+            OsType::MacOs => 1,
+            OsType::Linux => 2,
+            OsType::Android => 3,
+            OsType::Ios => 4,
+            OsType::Unix => 5,
+            OsType::Unknown(id) => id,
+        }
+    }
+    /// The OS this library was compiled for.
+    pub fn current() -> OsType {
+        if cfg!(target_os = "windows") {
+            OsType::Windows
+        } else if cfg!(target_os = "macos") {
+            OsType::MacOs
+        } else if cfg!(target_os = "android") {
+            OsType::Android
+        } else if cfg!(target_os = "ios") {
+            OsType::Ios
+        } else if cfg!(target_os = "linux") {
+            OsType::Linux
+        } else {
+            OsType::Unix
+        }
+    }
+}
+
+/// The version and OS of a client or server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub os_type: OsType,
+    pub version: String,
+}
+
+/// The licence of a server's sessions, as sent in `LICENCE_REQUIRED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LicenceType {
+    NoLicence,
+    CreativeCommons,
+    Unknown(u8),
+}
+impl LicenceType {
+    pub fn from_id(id: u8) -> LicenceType {
+        match id {
+            0 => LicenceType::NoLicence,
+            1 => LicenceType::CreativeCommons,
+            id => LicenceType::Unknown(id),
+        }
+    }
+    pub fn id(self) -> u8 {
+        match self {
+            LicenceType::NoLicence => 0,
+            LicenceType::CreativeCommons => 1,
+            LicenceType::Unknown(id) => id,
+        }
+    }
+}
+
+/// Whether the server is recording the session, as sent in `RECORDER_STATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecorderState {
+    Undefined,
+    NotInitialised,
+    Disabled,
+    Recording,
+}
+impl RecorderState {
+    /// Unknown values are treated as `Undefined`.
+    pub fn from_id(id: u8) -> RecorderState {
+        match id {
+            1 => RecorderState::NotInitialised,
+            2 => RecorderState::Disabled,
+            3 => RecorderState::Recording,
+            _ => RecorderState::Undefined,
+        }
+    }
+    pub fn id(self) -> u8 {
+        match self {
+            RecorderState::Undefined => 0,
+            RecorderState::NotInitialised => 1,
+            RecorderState::Disabled => 2,
+            RecorderState::Recording => 3,
+        }
+    }
+}
+
+/// Audio stream properties, as sent in `NETW_TRANSPORT_PROPS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkTransportProps {
+    /// Size of one coded audio frame in bytes, including the sequence number if any.
+    pub base_packet_size: u32,
+    pub block_size_factor: u16,
+    pub num_channels: u8,
+    pub sample_rate: u32,
+    /// 0 = none, 1 = CELT, 2 = Opus, 3 = Opus64.
+    pub codec: u16,
+    /// Bit 0: audio frames carry a sequence number.
+    pub flags: u16,
+    pub codec_options: u32,
+}
+impl NetworkTransportProps {
+    pub const CODEC_OPUS: u16 = 2;
+    pub const CODEC_OPUS64: u16 = 3;
+    pub const FLAG_SEQUENCE_NUMBER: u16 = 1;
+
+    /// Number of samples per channel in one coded audio frame.
+    pub fn frame_size(&self) -> usize {
+        if self.codec == Self::CODEC_OPUS64 {
+            64
+        } else {
+            128
+        }
+    }
+    pub fn has_sequence_numbers(&self) -> bool {
+        self.flags & Self::FLAG_SEQUENCE_NUMBER != 0
+    }
+    /// Size of one coded audio frame in bytes, without the sequence number.
+    pub fn coded_bytes(&self) -> usize {
+        let size = self.base_packet_size as usize;
+        if self.has_sequence_numbers() {
+            size.saturating_sub(1)
+        } else {
+            size
+        }
+    }
+    /// Size of an audio datagram: `block_size_factor` frames back to back.
+    pub fn audio_packet_size(&self) -> usize {
+        self.base_packet_size as usize * self.block_size_factor as usize
+    }
+    fn parse(bytes: &[u8]) -> IResult<&[u8], NetworkTransportProps> {
+        use nom::number::complete::{le_u16, le_u32, le_u8};
+        let (bytes, base_packet_size) = le_u32(bytes)?;
+        let (bytes, block_size_factor) = le_u16(bytes)?;
+        let (bytes, num_channels) = le_u8(bytes)?;
+        let (bytes, sample_rate) = le_u32(bytes)?;
+        let (bytes, codec) = le_u16(bytes)?;
+        let (bytes, flags) = le_u16(bytes)?;
+        let (bytes, codec_options) = le_u32(bytes)?;
+        Ok((
+            bytes,
+            NetworkTransportProps {
+                base_packet_size,
+                block_size_factor,
+                num_channels,
+                sample_rate,
+                codec,
+                flags,
+                codec_options,
+            },
+        ))
+    }
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.base_packet_size.to_le_bytes());
+        bytes.extend_from_slice(&self.block_size_factor.to_le_bytes());
+        bytes.push(self.num_channels);
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&self.codec.to_le_bytes());
+        bytes.extend_from_slice(&self.flags.to_le_bytes());
+        bytes.extend_from_slice(&self.codec_options.to_le_bytes());
+    }
+}
+
+/// Our own channel information, as sent in `CHANNEL_INFOS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelInfo {
+    pub country: Country,
+    pub instrument: Instrument,
+    pub skill_level: u8,
+    pub name: String,
+    pub city: String,
+}
+impl ChannelInfo {
+    fn parse(bytes: &[u8]) -> IResult<&[u8], ChannelInfo> {
+        use nom::number::complete::le_u8;
+        let (bytes, country) = parse_country(bytes)?;
+        let (bytes, instrument) = parse_instrument(bytes)?;
+        let (bytes, skill_level) = le_u8(bytes)?;
+        let (bytes, name) = parse_string(bytes)?;
+        let (bytes, city) = parse_string(bytes)?;
+        Ok((
+            bytes,
+            ChannelInfo {
+                country,
+                instrument,
+                skill_level,
+                name,
+                city,
+            },
+        ))
+    }
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.country.id().to_le_bytes());
+        bytes.extend_from_slice(&self.instrument.id().to_le_bytes());
+        bytes.push(self.skill_level);
+        write_string(bytes, &self.name);
+        write_string(bytes, &self.city);
+    }
+}
+
+/// A client connected to the server, as listed in `CONN_CLIENTS_LIST`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub channel_id: u8,
+    pub country: Country,
+    pub instrument: Instrument,
+    pub skill_level: u8,
+    pub name: String,
+    pub city: String,
+}
+#[cfg(test)]
+impl ClientInfo {
+    /// A client with only a channel and a name, for tests.
+    pub(crate) fn named(channel_id: u8, name: &str) -> ClientInfo {
+        ClientInfo {
+            channel_id,
+            country: Country::AnyCountry,
+            instrument: Instrument::None,
+            skill_level: 0,
+            name: String::from(name),
+            city: String::new(),
+        }
+    }
+}
+impl ClientInfo {
+    fn parse(bytes: &[u8]) -> IResult<&[u8], ClientInfo> {
+        use nom::number::complete::{le_u32, le_u8};
+        let (bytes, channel_id) = le_u8(bytes)?;
+        let (bytes, country) = parse_country(bytes)?;
+        let (bytes, instrument) = parse_instrument(bytes)?;
+        let (bytes, skill_level) = le_u8(bytes)?;
+        // The IP address is always sent as zero.
+        let (bytes, _ip) = le_u32(bytes)?;
+        let (bytes, name) = parse_string(bytes)?;
+        let (bytes, city) = parse_string(bytes)?;
+        Ok((
+            bytes,
+            ClientInfo {
+                channel_id,
+                country,
+                instrument,
+                skill_level,
+                name,
+                city,
+            },
+        ))
+    }
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.channel_id);
+        bytes.extend_from_slice(&self.country.id().to_le_bytes());
+        bytes.extend_from_slice(&self.instrument.id().to_le_bytes());
+        bytes.push(self.skill_level);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        write_string(bytes, &self.name);
+        write_string(bytes, &self.city);
+    }
+}
+
+/// A server registered with a directory, as listed in `CLM_SERVER_LIST`.
+///
+/// Servers from `CLM_RED_SERVER_LIST` only have an address and a name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub address: SocketAddr,
+    pub name: String,
+    pub city: String,
+    pub country: Country,
+    pub max_clients: u8,
+    pub permanent: bool,
+}
+impl ServerInfo {
+    fn parse(bytes: &[u8]) -> IResult<&[u8], ServerInfo> {
+        use nom::number::complete::le_u8;
+        let (bytes, address) = parse_address(bytes)?;
+        let (bytes, country) = parse_country(bytes)?;
+        let (bytes, max_clients) = le_u8(bytes)?;
+        let (bytes, permanent) = le_u8(bytes)?;
+        let (bytes, name) = parse_string(bytes)?;
+        // The internal address is only useful to clients behind the same NAT.
+        let (bytes, _internal_address) = parse_string(bytes)?;
+        let (bytes, city) = parse_string(bytes)?;
+        Ok((
+            bytes,
+            ServerInfo {
+                address,
+                name,
+                city,
+                country,
+                max_clients,
+                permanent: permanent != 0,
+            },
+        ))
+    }
+    fn parse_reduced(bytes: &[u8]) -> IResult<&[u8], ServerInfo> {
+        let (bytes, address) = parse_address(bytes)?;
+        let (rest, len) = nom::number::complete::le_u8(bytes)?;
+        let (rest, name) = nom::bytes::complete::take(len)(rest)?;
+        let name = match std::str::from_utf8(name) {
+            Ok(s) => String::from(s),
+            Err(_) => {
+                return Err(nom::Err::Failure(nom::error::make_error(
+                    bytes,
+                    nom::error::ErrorKind::Satisfy,
+                )))
+            }
+        };
+        Ok((
+            rest,
+            ServerInfo {
+                address,
+                name,
+                city: String::new(),
+                country: Country::AnyCountry,
+                max_clients: 0,
+                permanent: false,
+            },
+        ))
+    }
+    fn write(&self, bytes: &mut Vec<u8>) {
+        write_address(bytes, &self.address);
+        bytes.extend_from_slice(&self.country.id().to_le_bytes());
+        bytes.push(self.max_clients);
+        bytes.push(self.permanent as u8);
+        write_string(bytes, &self.name);
+        write_string(bytes, "");
+        write_string(bytes, &self.city);
+    }
+    fn write_reduced(&self, bytes: &mut Vec<u8>) {
+        write_address(bytes, &self.address);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+    }
+}
+
+/// Parses a country ID. Unknown IDs (e.g. from newer clients) are kept as is.
+fn parse_country(bytes: &[u8]) -> IResult<&[u8], Country> {
+    let (bytes, id) = nom::number::complete::le_u16(bytes)?;
+    Ok((bytes, Country::from_id(id)))
+}
+
+/// Parses an instrument ID. Unknown IDs are kept as is.
+fn parse_instrument(bytes: &[u8]) -> IResult<&[u8], Instrument> {
+    let (bytes, id) = nom::number::complete::le_u32(bytes)?;
+    Ok((bytes, Instrument::from_id(id)))
+}
+
+/// Parses a list of items that must span the whole input.
+fn parse_all<'a, T>(
+    parse: impl FnMut(&'a [u8]) -> IResult<&'a [u8], T>,
+) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Vec<T>> {
+    nom::sequence::terminated(
+        nom::multi::many0(nom::combinator::complete(parse)),
+        nom::combinator::eof,
+    )
+}
+
+/// Parses an IPv4 address (as `u32`) followed by a port.
+fn parse_address(bytes: &[u8]) -> IResult<&[u8], SocketAddr> {
+    let (bytes, ip) = nom::number::complete::le_u32(bytes)?;
+    let (bytes, port) = nom::number::complete::le_u16(bytes)?;
+    Ok((bytes, SocketAddr::new(Ipv4Addr::from(ip).into(), port)))
+}
+
+fn write_address(bytes: &mut Vec<u8>, address: &SocketAddr) {
+    let ip = match address.ip() {
+        IpAddr::V4(ip) => u32::from(ip),
+        IpAddr::V6(_) => 0,
+    };
+    bytes.extend_from_slice(&ip.to_le_bytes());
+    bytes.extend_from_slice(&address.port().to_le_bytes());
+}
+
+/// Parses a UTF-8 string prefixed by its length in bytes as `u16`.
+fn parse_string(bytes: &[u8]) -> IResult<&[u8], String> {
+    let (rest, len) = nom::number::complete::le_u16(bytes)?;
+    let (rest, string) = nom::bytes::complete::take(len)(rest)?;
+    match std::str::from_utf8(string) {
+        Ok(s) => Ok((rest, String::from(s))),
+        Err(_) => Err(nom::Err::Failure(nom::error::make_error(
+            bytes,
+            nom::error::ErrorKind::Satisfy,
+        ))),
+    }
+}
+
+fn parse_version_and_os(bytes: &[u8]) -> IResult<&[u8], (OsType, String)> {
+    let (bytes, os_type) = nom::number::complete::le_u8(bytes)?;
+    let os_type = OsType::from_id(os_type);
+    let (bytes, version) = parse_string(bytes)?;
+    Ok((bytes, (os_type, version)))
+}
+
+pub(crate) fn is_connectionless_id(id: u16) -> bool {
+    (1000..2000).contains(&id)
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(&(string.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+#[derive(Debug)]
+pub(crate) struct Message<'a> {
+    pub id: u16,
+    pub counter: u8,
+    pub data: &'a [u8],
+}
+impl Message<'_> {
+    pub fn parse(input_bytes: &[u8]) -> IResult<&[u8], Message<'_>> {
+        // Use `nom` to parse the message.
+        // All numbers are in little endian.
+        let bytes = input_bytes;
+
+        // First two bytes are 0x00 0x00.
+        let (bytes, _) = nom::bytes::complete::tag([0x00, 0x00])(bytes)?;
+
+        // Next two bytes are the message ID.
+        let (bytes, id) = nom::number::complete::le_u16(bytes)?;
+
+        // The next byte is the counter.
+        let (bytes, counter) = nom::number::complete::le_u8(bytes)?;
+
+        // The next two bytes are the length of the data.
+        let (bytes, len) = nom::number::complete::le_u16(bytes)?;
+
+        // The next `len` bytes are the data.
+        let (bytes, data) = nom::bytes::complete::take(len)(bytes)?;
+
+        // Verify the checksum.
+        let expected = crc::crc(&input_bytes[0..((len as usize) + 7)]).to_le_bytes();
+
+        // Finally, two more bytes for the checksum.
+        let (bytes, _) = nom::bytes::complete::tag(expected)(bytes)?;
+
+        // Return the parsed message.
+        Ok((bytes, Message { id, counter, data }))
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + self.data.len());
+        bytes.extend_from_slice(&[0x00, 0x00]);
+        bytes.extend_from_slice(&self.id.to_le_bytes());
+        bytes.push(self.counter);
+        bytes.extend_from_slice(&(self.data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.data);
+        let crc = crc::crc(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: ProtocolMessage) {
+        let datagram = message.to_datagram(7);
+        let (_, frame) = Message::parse(&datagram).unwrap();
+        assert_eq!(frame.id, message.id());
+        assert_eq!(frame.counter, 7);
+        assert_eq!(ProtocolMessage::decode(frame.id, frame.data), Ok(message));
+    }
+
+    #[test]
+    fn decodes_channel_infos() {
+        let data = [
+            0xd3, 0x00, 0x07, 0x00, 0x00, 0x00, 0x01, 0x0f, 0x00, 0x64, 0x74, 0x69, 0x6e, 0x74,
+            0x68, 0x20, 0x2f, 0x20, 0x6c, 0x69, 0x73, 0x74, 0x65, 0x6e, 0x00, 0x00,
+        ];
+        assert_eq!(
+            ProtocolMessage::decode(25, &data),
+            Ok(ProtocolMessage::ChannelInfos(ChannelInfo {
+                country: Country::Thailand,
+                instrument: Instrument::Synthesizer,
+                skill_level: 1,
+                name: String::from("dtinth / listen"),
+                city: String::from(""),
+            }))
+        );
+    }
+
+    #[test]
+    fn encodes_chat_text() {
+        let message = ProtocolMessage::ChatText {
+            text: String::from("hi ✋"),
+        };
+        assert_eq!(
+            message.to_datagram(3),
+            vec![
+                0x00, 0x00, 0x12, 0x00, 0x03, 0x08, 0x00, 0x06, 0x00, 0x68, 0x69, 0x20, 0xe2, 0x9c,
+                0x8b, 0xb4, 0xf8
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        roundtrip(ProtocolMessage::Acknowledgement { message_id: 24 });
+        roundtrip(ProtocolMessage::JitterBufferSize { blocks: 4 });
+        roundtrip(ProtocolMessage::RequestJitterBufferSize);
+        roundtrip(ProtocolMessage::ChannelGain {
+            channel_id: 3,
+            gain: 0x8000,
+        });
+        roundtrip(ProtocolMessage::ChatText {
+            text: String::from("สวัสดี"),
+        });
+        roundtrip(ProtocolMessage::NetworkTransportProps(
+            NetworkTransportProps {
+                base_packet_size: 166,
+                block_size_factor: 2,
+                num_channels: 2,
+                sample_rate: 48000,
+                codec: 2,
+                flags: 1,
+                codec_options: 0,
+            },
+        ));
+        roundtrip(ProtocolMessage::ConnectedClientsList {
+            clients: vec![
+                ClientInfo {
+                    channel_id: 0,
+                    country: Country::Thailand,
+                    instrument: Instrument::Listener,
+                    skill_level: 3,
+                    name: String::from("listener"),
+                    city: String::from("Bangkok"),
+                },
+                ClientInfo {
+                    channel_id: 5,
+                    country: Country::AnyCountry,
+                    instrument: Instrument::DrumSet,
+                    skill_level: 0,
+                    name: String::from(""),
+                    city: String::from(""),
+                },
+                // IDs from newer clients survive a roundtrip.
+                ClientInfo {
+                    channel_id: 6,
+                    country: Country::Unknown(300),
+                    instrument: Instrument::Unknown(50),
+                    skill_level: 1,
+                    name: String::from("new"),
+                    city: String::new(),
+                },
+            ],
+        });
+        roundtrip(ProtocolMessage::ConnectedClientsList { clients: vec![] });
+        roundtrip(ProtocolMessage::VersionAndOs {
+            os_type: OsType::Linux,
+            version: String::from("3.8.1"),
+        });
+        roundtrip(ProtocolMessage::MuteStateChanged {
+            channel_id: 1,
+            muted: true,
+        });
+        roundtrip(ProtocolMessage::RecorderState {
+            state: RecorderState::Recording,
+        });
+        roundtrip(ProtocolMessage::LicenceRequired {
+            licence_type: LicenceType::CreativeCommons,
+        });
+        roundtrip(ProtocolMessage::SplitMessage {
+            message_id: 24,
+            num_parts: 3,
+            part_index: 1,
+            data: vec![1, 2, 3],
+        });
+        roundtrip(ProtocolMessage::ClmPingMsWithNumClients {
+            time_ms: 123456,
+            num_clients: 9,
+        });
+        roundtrip(ProtocolMessage::ClmVersionAndOs {
+            os_type: OsType::Windows,
+            version: String::from("3.9.0"),
+        });
+        roundtrip(ProtocolMessage::ClmRequestConnectedClientsList);
+        roundtrip(ProtocolMessage::ClmSendEmptyMessage {
+            address: "203.0.113.7:22124".parse().unwrap(),
+        });
+        roundtrip(ProtocolMessage::Unknown {
+            id: 999,
+            data: vec![4, 5],
+        });
+    }
+
+    #[test]
+    fn channel_levels_are_packed_in_nibbles() {
+        let message = ProtocolMessage::ClmChannelLevelList {
+            levels: vec![1, 2, 9],
+        };
+        assert_eq!(message.encode(), vec![0x21, 0xf9]);
+        // The padding nibble comes back as a level.
+        assert_eq!(
+            ProtocolMessage::decode(CLM_CHANNEL_LEVEL_LIST, &[0x21, 0xf9]),
+            Ok(ProtocolMessage::ClmChannelLevelList {
+                levels: vec![1, 2, 9, 15]
+            })
+        );
+        roundtrip(ProtocolMessage::ClmChannelLevelList {
+            levels: vec![0, 15, 3, 4],
+        });
+        // A real level of 15 for the last of an even number of clients is kept.
+        roundtrip(ProtocolMessage::ClmChannelLevelList {
+            levels: vec![3, 15],
+        });
+    }
+
+    #[test]
+    fn server_lists_roundtrip() {
+        let server = ServerInfo {
+            address: "192.0.2.1:22124".parse().unwrap(),
+            name: String::from("Jam"),
+            city: String::from("Bangkok"),
+            country: Country::Thailand,
+            max_clients: 10,
+            permanent: true,
+        };
+        let mut bytes = vec![];
+        server.write(&mut bytes);
+        assert_eq!(&bytes[0..6], &[0x01, 0x02, 0x00, 0xc0, 0x6c, 0x56]);
+        roundtrip(ProtocolMessage::ClmServerList {
+            servers: vec![server.clone(), server],
+        });
+        roundtrip(ProtocolMessage::ClmReducedServerList {
+            servers: vec![ServerInfo {
+                address: "198.51.100.2:22125".parse().unwrap(),
+                name: String::from("Reduced"),
+                city: String::new(),
+                country: Country::AnyCountry,
+                max_clients: 0,
+                permanent: false,
+            }],
+        });
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        assert_eq!(
+            ProtocolMessage::decode(13, &[1, 0]),
+            Err(DecodeError { id: 13 })
+        );
+        assert_eq!(
+            ProtocolMessage::decode(24, &[1, 2, 3]),
+            Err(DecodeError { id: 24 })
+        );
+        assert_eq!(
+            ProtocolMessage::decode(1002, &[1, 0, 0, 0]),
+            Err(DecodeError { id: 1002 })
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Reconnecting after the server goes away.
+//!
+//! A session ends when the server stops sending datagrams for a while or
+//! answers with `CLM_SERVER_FULL`. The supervisor in
+//! [`JamulusClient::run_with_reconnect`](crate::JamulusClient::run_with_reconnect)
+//! then waits with exponential backoff and starts a new session. Jamulus
+//! servers create a channel for any client that sends audio, so reconnecting
+//! only means sending audio again; our identity and mix are sent when the
+//! server asks for them.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When to give up on a session and how long to wait before the next one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The session is considered lost after this long without any datagram.
+    pub timeout: Duration,
+    /// Delay before the first reconnection attempt.
+    pub initial_delay: Duration,
+    /// The delay doubles with every attempt, up to this limit.
+    pub max_delay: Duration,
+    /// Gives up after this many attempts in a row, or never if `None`.
+    pub max_attempts: Option<u32>,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            timeout: Duration::from_secs(10),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Why a session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// We were told to disconnect.
+    Disconnected,
+    /// No datagram arrived within the policy's timeout.
+    TimedOut,
+    /// The server answered with `CLM_SERVER_FULL`.
+    ServerFull,
+}
+
+/// Reported through `Handler::on_reconnect_event`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconnectEvent {
+    /// The session ended, for a reason other than being told to disconnect.
+    SessionEnded(SessionEnd),
+    /// Waiting `delay` before reconnection attempt number `attempt` (from 1).
+    Reconnecting { attempt: u32, delay: Duration },
+    /// A new session started: the server sent us something again.
+    Reconnected,
+    /// `max_attempts` were made without success.
+    GaveUp,
+}
+
+/// Computes the delays between attempts: exponential, capped, with jitter
+/// so that clients dropped at the same time do not come back all at once.
+pub(crate) struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    attempt: u32,
+    random_state: u64,
+}
+impl Backoff {
+    pub fn new(policy: &ReconnectPolicy) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::with_seed(policy, seed)
+    }
+    fn with_seed(policy: &ReconnectPolicy, seed: u64) -> Self {
+        Backoff {
+            initial_delay: policy.initial_delay,
+            max_delay: policy.max_delay,
+            attempt: 0,
+            // Xorshift gets stuck at zero.
+            random_state: seed | 1,
+        }
+    }
+
+    /// Number of attempts so far.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Starts over after a successful reconnection.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// The delay before the next attempt: between half and all of
+    /// `initial_delay * 2^attempt`, but never more than `max_delay`.
+    pub fn next_delay(&mut self) -> Duration {
+        let factor = 1u32 << self.attempt.min(16);
+        let delay = self
+            .initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        self.attempt += 1;
+        delay / 2 + delay.mul_f64(self.next_random() / 2.0)
+    }
+
+    /// A number in `[0, 1)`.
+    fn next_random(&mut self) -> f64 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random_state = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            ..ReconnectPolicy::default()
+        };
+        let mut backoff = Backoff::with_seed(&policy, 42);
+        for base in [1, 2, 4, 8, 10, 10, 10] {
+            let delay = backoff.next_delay();
+            let base = Duration::from_secs(base);
+            assert!(delay >= base / 2 && delay <= base, "{:?}", delay);
+        }
+        assert_eq!(backoff.attempt(), 7);
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_spreads_delays() {
+        let policy = ReconnectPolicy::default();
+        let delays: Vec<_> = (0..10)
+            .map(|seed| Backoff::with_seed(&policy, seed * 7919).next_delay())
+            .collect();
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+}
@@ -0,0 +1,229 @@
+//! Reliable delivery of protocol messages.
+//!
+//! Like the reference client, only one outgoing message is in flight at a
+//! time. It is sent again until the server acknowledges it with `ACKN`, and
+//! the next message is only sent after that.
+//!
+//! The server does the same, so an incoming message is received again when
+//! our `ACKN` is lost. Such duplicates must be acknowledged but not handled.
+
+use crate::protocol::ProtocolMessage;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Time to wait for an acknowledgement before sending a message again.
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(400);
+
+/// Number of times a message is sent before giving up on it.
+pub const MAX_TRANSMISSIONS: u32 = 10;
+
+/// Number of received `(id, counter)` pairs remembered to detect duplicates.
+/// Counters wrap after 256 messages, so this must stay well below that.
+const RECENT_MESSAGES: usize = 16;
+
+pub enum Outgoing {
+    /// A datagram to be sent now.
+    Send(Vec<u8>),
+    /// A message that was never acknowledged and has been dropped.
+    Failed(ProtocolMessage),
+}
+
+struct Pending {
+    counter: u8,
+    message: ProtocolMessage,
+    transmissions: u32,
+    last_sent: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct SendQueue {
+    next_counter_id: u8,
+    queue: VecDeque<Pending>,
+}
+impl SendQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a message, assigning it the next counter.
+    pub fn push(&mut self, message: ProtocolMessage) {
+        self.queue.push_back(Pending {
+            counter: self.next_counter_id,
+            message,
+            transmissions: 0,
+            last_sent: None,
+        });
+        self.next_counter_id = self.next_counter_id.wrapping_add(1);
+    }
+
+    /// Handles an `ACKN`. Returns true if it acknowledged the message in flight.
+    pub fn acknowledge(&mut self, message_id: u16, counter: u8) -> bool {
+        match self.queue.front() {
+            Some(front)
+                if front.last_sent.is_some()
+                    && front.counter == counter
+                    && front.message.id() == message_id =>
+            {
+                self.queue.pop_front();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Empties the queue, e.g. when the session ends, returning the messages
+    /// that were never acknowledged. Counters start over.
+    pub fn take_pending(&mut self) -> Vec<ProtocolMessage> {
+        self.next_counter_id = 0;
+        self.queue
+            .drain(..)
+            .map(|pending| pending.message)
+            .collect()
+    }
+
+    /// Returns the next thing to do, if any. Call repeatedly until it returns `None`.
+    pub fn poll(&mut self, now: Instant) -> Option<Outgoing> {
+        let front = self.queue.front_mut()?;
+        if let Some(last_sent) = front.last_sent {
+            if now.duration_since(last_sent) < RETRANSMIT_TIMEOUT {
+                return None;
+            }
+            if front.transmissions >= MAX_TRANSMISSIONS {
+                let failed = self.queue.pop_front()?;
+                return Some(Outgoing::Failed(failed.message));
+            }
+        }
+        front.transmissions += 1;
+        front.last_sent = Some(now);
+        Some(Outgoing::Send(front.message.to_datagram(front.counter)))
+    }
+}
+
+/// Remembers recently received messages to detect retransmissions.
+#[derive(Default)]
+pub struct ReceivedMessages {
+    recent: VecDeque<(u16, u8)>,
+}
+impl ReceivedMessages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a received message. Returns false if it is a duplicate.
+    pub fn insert(&mut self, id: u16, counter: u8) -> bool {
+        if self.recent.contains(&(id, counter)) {
+            return false;
+        }
+        if self.recent.len() == RECENT_MESSAGES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((id, counter));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent_counter(outgoing: Option<Outgoing>) -> u8 {
+        match outgoing {
+            Some(Outgoing::Send(datagram)) => datagram[4],
+            _ => panic!("expected a datagram to be sent"),
+        }
+    }
+
+    #[test]
+    fn sends_one_message_at_a_time() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new();
+        queue.push(ProtocolMessage::RequestConnectedClientsList);
+        queue.push(ProtocolMessage::JitterBufferSize { blocks: 4 });
+
+        assert_eq!(sent_counter(queue.poll(now)), 0);
+        assert!(queue.poll(now).is_none());
+
+        // Acknowledgements for other messages are ignored.
+        assert!(!queue.acknowledge(10, 0));
+        assert!(!queue.acknowledge(16, 1));
+
+        assert!(queue.acknowledge(16, 0));
+        assert_eq!(sent_counter(queue.poll(now)), 1);
+        assert!(queue.acknowledge(10, 1));
+        assert!(queue.poll(now + RETRANSMIT_TIMEOUT).is_none());
+    }
+
+    #[test]
+    fn retransmits_until_acknowledged() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new();
+        queue.push(ProtocolMessage::RequestConnectedClientsList);
+
+        assert_eq!(sent_counter(queue.poll(now)), 0);
+        assert!(queue.poll(now + Duration::from_millis(100)).is_none());
+        assert_eq!(sent_counter(queue.poll(now + RETRANSMIT_TIMEOUT)), 0);
+        assert!(queue.acknowledge(16, 0));
+        assert!(queue.poll(now + RETRANSMIT_TIMEOUT * 2).is_none());
+    }
+
+    #[test]
+    fn gives_up_after_max_transmissions() {
+        let mut now = Instant::now();
+        let mut queue = SendQueue::new();
+        queue.push(ProtocolMessage::ChannelGain {
+            channel_id: 1,
+            gain: 0x8000,
+        });
+        queue.push(ProtocolMessage::RequestConnectedClientsList);
+
+        for _ in 0..MAX_TRANSMISSIONS {
+            assert_eq!(sent_counter(queue.poll(now)), 0);
+            now += RETRANSMIT_TIMEOUT;
+        }
+        match queue.poll(now) {
+            Some(Outgoing::Failed(message)) => assert_eq!(message.id(), 13),
+            _ => panic!("expected the message to fail"),
+        }
+        assert_eq!(sent_counter(queue.poll(now)), 1);
+    }
+
+    #[test]
+    fn takes_pending_messages() {
+        let now = Instant::now();
+        let mut queue = SendQueue::new();
+        queue.push(ProtocolMessage::RequestConnectedClientsList);
+        queue.push(ProtocolMessage::JitterBufferSize { blocks: 4 });
+        assert_eq!(sent_counter(queue.poll(now)), 0);
+
+        assert_eq!(
+            queue.take_pending(),
+            vec![
+                ProtocolMessage::RequestConnectedClientsList,
+                ProtocolMessage::JitterBufferSize { blocks: 4 },
+            ]
+        );
+        assert!(queue.poll(now).is_none());
+        queue.push(ProtocolMessage::RequestConnectedClientsList);
+        assert_eq!(sent_counter(queue.poll(now)), 0);
+    }
+
+    #[test]
+    fn detects_duplicate_messages() {
+        let mut received = ReceivedMessages::new();
+        assert!(received.insert(24, 5));
+        assert!(received.insert(18, 6));
+        assert!(!received.insert(24, 5));
+        assert!(!received.insert(18, 6));
+        assert!(received.insert(18, 7));
+    }
+
+    #[test]
+    fn forgets_old_messages() {
+        let mut received = ReceivedMessages::new();
+        for counter in 0..=255u8 {
+            assert!(received.insert(13, counter));
+        }
+        // The counter wrapped around: this is a new message.
+        assert!(received.insert(13, 0));
+    }
+}
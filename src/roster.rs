@@ -0,0 +1,226 @@
+//! The clients connected to the server, as we know them.
+
+use crate::protocol::{ClientInfo, RecorderState};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Roster {
+    /// Our own channel, once the server has sent `CLIENT_ID`.
+    pub own_channel_id: Option<u8>,
+    /// The clients from the last `CONN_CLIENTS_LIST`, including ourselves.
+    pub clients: Vec<ClientInfo>,
+    /// `(channel_id, level)` pairs from the last `CLM_CHANNEL_LEVEL_LIST`,
+    /// with levels from 0 (silent) upwards. Only updated if channel levels
+    /// were enabled.
+    pub levels: Vec<(u8, u8)>,
+    /// Channels that have muted us in their mix (`MUTE_STATE_CHANGED`).
+    pub muted_by: Vec<u8>,
+    /// Whether the server is recording, once it has told us.
+    pub recorder_state: Option<RecorderState>,
+}
+impl Roster {
+    pub fn client(&self, channel_id: u8) -> Option<&ClientInfo> {
+        self.clients
+            .iter()
+            .find(|client| client.channel_id == channel_id)
+    }
+    /// Our own entry in the client list.
+    pub fn own_client(&self) -> Option<&ClientInfo> {
+        self.client(self.own_channel_id?)
+    }
+    pub fn level(&self, channel_id: u8) -> Option<u8> {
+        self.levels
+            .iter()
+            .find(|(id, _)| *id == channel_id)
+            .map(|(_, level)| *level)
+    }
+    /// Records that a channel has (un)muted us.
+    pub(crate) fn set_muted_by(&mut self, channel_id: u8, muted: bool) {
+        self.muted_by.retain(|id| *id != channel_id);
+        if muted {
+            self.muted_by.push(channel_id);
+        }
+    }
+    /// Replaces the client list, forgetting mutes by clients that left.
+    pub(crate) fn set_clients(&mut self, clients: Vec<ClientInfo>) {
+        self.clients = clients;
+        let clients = &self.clients;
+        self.muted_by
+            .retain(|id| clients.iter().any(|client| client.channel_id == *id));
+    }
+    /// Stores levels from `CLM_CHANNEL_LEVEL_LIST`, which come in the same
+    /// order as the client list. Extra levels, such as the padding nibble, are dropped.
+    pub(crate) fn set_levels(&mut self, levels: &[u8]) {
+        self.levels = self
+            .clients
+            .iter()
+            .zip(levels)
+            .map(|(client, level)| (client.channel_id, *level))
+            .collect();
+    }
+}
+
+/// A client appeared in the client list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientJoined {
+    pub timestamp: SystemTime,
+    pub client: ClientInfo,
+}
+
+/// A client disappeared from the client list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientLeft {
+    pub timestamp: SystemTime,
+    pub client: ClientInfo,
+}
+
+/// A client changed its name, city, country, instrument or skill level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfoChanged {
+    pub timestamp: SystemTime,
+    pub old: ClientInfo,
+    pub new: ClientInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientEvent {
+    Joined(ClientJoined),
+    Left(ClientLeft),
+    InfoChanged(ClientInfoChanged),
+}
+
+/// Compares two successive client lists, matching clients by channel ID.
+///
+/// The first list after connecting reports everyone already there as joined.
+pub fn diff(old: &[ClientInfo], new: &[ClientInfo], timestamp: SystemTime) -> Vec<ClientEvent> {
+    let find = |clients: &[ClientInfo], channel_id: u8| {
+        clients
+            .iter()
+            .find(|client| client.channel_id == channel_id)
+            .cloned()
+    };
+    let mut events = Vec::new();
+    for client in old {
+        if find(new, client.channel_id).is_none() {
+            events.push(ClientEvent::Left(ClientLeft {
+                timestamp,
+                client: client.clone(),
+            }));
+        }
+    }
+    for client in new {
+        match find(old, client.channel_id) {
+            None => events.push(ClientEvent::Joined(ClientJoined {
+                timestamp,
+                client: client.clone(),
+            })),
+            Some(old) if old != *client => {
+                events.push(ClientEvent::InfoChanged(ClientInfoChanged {
+                    timestamp,
+                    old,
+                    new: client.clone(),
+                }))
+            }
+            Some(_) => {}
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tables::Country;
+
+    #[test]
+    fn diffs_client_lists() {
+        let now = SystemTime::now();
+        let old = [
+            ClientInfo::named(0, "Alice"),
+            ClientInfo::named(1, "Bob"),
+            ClientInfo::named(2, "Carol"),
+        ];
+        let new = [
+            ClientInfo::named(0, "Alice"),
+            ClientInfo::named(2, "Caroline"),
+            ClientInfo::named(3, "Dave"),
+        ];
+        assert_eq!(
+            diff(&old, &new, now),
+            vec![
+                ClientEvent::Left(ClientLeft {
+                    timestamp: now,
+                    client: ClientInfo::named(1, "Bob"),
+                }),
+                ClientEvent::InfoChanged(ClientInfoChanged {
+                    timestamp: now,
+                    old: ClientInfo::named(2, "Carol"),
+                    new: ClientInfo::named(2, "Caroline"),
+                }),
+                ClientEvent::Joined(ClientJoined {
+                    timestamp: now,
+                    client: ClientInfo::named(3, "Dave"),
+                }),
+            ]
+        );
+        assert_eq!(diff(&new, &new, now), vec![]);
+    }
+
+    #[test]
+    fn maps_levels_onto_clients() {
+        let mut roster = Roster {
+            clients: vec![
+                ClientInfo::named(4, "Alice"),
+                ClientInfo::named(1, "Bob"),
+                ClientInfo::named(7, "Carol"),
+            ],
+            ..Roster::default()
+        };
+        // Three clients: the padding nibble is left over.
+        roster.set_levels(&[9, 0, 3, 15]);
+        assert_eq!(roster.levels, vec![(4, 9), (1, 0), (7, 3)]);
+        assert_eq!(roster.level(7), Some(3));
+        assert_eq!(roster.level(2), None);
+
+        // A list for fewer clients, sent before the new client list arrived.
+        roster.set_levels(&[5]);
+        assert_eq!(roster.levels, vec![(4, 5)]);
+    }
+
+    #[test]
+    fn tracks_who_muted_us() {
+        let mut roster = Roster::default();
+        roster.set_clients(vec![
+            ClientInfo::named(1, "Alice"),
+            ClientInfo::named(2, "Bob"),
+        ]);
+        roster.set_muted_by(1, true);
+        roster.set_muted_by(2, true);
+        roster.set_muted_by(1, true);
+        roster.set_muted_by(2, false);
+        assert_eq!(roster.muted_by, vec![1]);
+
+        roster.set_clients(vec![ClientInfo::named(2, "Bob")]);
+        assert!(roster.muted_by.is_empty());
+    }
+
+    #[test]
+    fn finds_own_client() {
+        let mut roster = Roster::default();
+        roster.clients.push(ClientInfo {
+            country: Country::Thailand,
+            ..ClientInfo::named(3, "listener")
+        });
+        assert_eq!(roster.own_client(), None);
+        roster.own_channel_id = Some(3);
+        assert_eq!(
+            roster.own_client().map(|client| &client.name[..]),
+            Some("listener")
+        );
+        assert_eq!(
+            serde_json::to_value(&roster).unwrap()["clients"][0]["country"],
+            "Thailand"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Splitting and reassembly of `SPECIAL_SPLIT_MESSAGE` parts.
+
+use crate::protocol::ProtocolMessage;
+
+/// Largest payload sent as a single message, as in the reference implementation.
+/// Larger payloads are split into parts of this size.
+pub const MAX_PART_SIZE: usize = 550;
+
+/// Splits a message into `SplitMessage` parts if its payload is too large.
+/// Small messages are returned as is.
+pub fn split_message(message: ProtocolMessage) -> Vec<ProtocolMessage> {
+    let data = message.encode();
+    if data.len() <= MAX_PART_SIZE {
+        return vec![message];
+    }
+    let num_parts = data.len().div_ceil(MAX_PART_SIZE) as u8;
+    data.chunks(MAX_PART_SIZE)
+        .enumerate()
+        .map(|(part_index, chunk)| ProtocolMessage::SplitMessage {
+            message_id: message.id(),
+            num_parts,
+            part_index: part_index as u8,
+            data: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Collects the parts of one split message at a time.
+///
+/// Parts may arrive in any order. When a part of another message arrives
+/// before the current one is complete, the incomplete message is dropped.
+#[derive(Default)]
+pub struct SplitAssembler {
+    message_id: u16,
+    parts: Vec<Option<Vec<u8>>>,
+}
+impl SplitAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a part, returning the message ID and the reassembled payload
+    /// once all parts have been received.
+    pub fn put(
+        &mut self,
+        message_id: u16,
+        num_parts: u8,
+        part_index: u8,
+        data: Vec<u8>,
+    ) -> Option<(u16, Vec<u8>)> {
+        if part_index >= num_parts {
+            return None;
+        }
+        if message_id != self.message_id || num_parts as usize != self.parts.len() {
+            self.message_id = message_id;
+            self.parts = vec![None; num_parts as usize];
+        }
+        self.parts[part_index as usize] = Some(data);
+        if self.parts.iter().any(|part| part.is_none()) {
+            return None;
+        }
+        let payload = self.parts.drain(..).flatten().flatten().collect();
+        Some((message_id, payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn large_chat_text() -> ProtocolMessage {
+        ProtocolMessage::ChatText {
+            text: "0123456789".repeat(120),
+        }
+    }
+
+    fn put_all(
+        assembler: &mut SplitAssembler,
+        parts: &[ProtocolMessage],
+    ) -> Option<(u16, Vec<u8>)> {
+        let mut result = None;
+        for part in parts {
+            if let ProtocolMessage::SplitMessage {
+                message_id,
+                num_parts,
+                part_index,
+                data,
+            } = part.clone()
+            {
+                result = assembler.put(message_id, num_parts, part_index, data);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn small_messages_are_not_split() {
+        let message = ProtocolMessage::ChatText {
+            text: String::from("hello"),
+        };
+        assert_eq!(split_message(message.clone()), vec![message]);
+    }
+
+    #[test]
+    fn split_parts_reassemble_in_order() {
+        let message = large_chat_text();
+        let parts = split_message(message.clone());
+        assert_eq!(parts.len(), 3);
+        let mut assembler = SplitAssembler::new();
+        let (id, data) = put_all(&mut assembler, &parts).unwrap();
+        assert_eq!(ProtocolMessage::decode(id, &data), Ok(message));
+    }
+
+    #[test]
+    fn split_parts_reassemble_out_of_order() {
+        let message = large_chat_text();
+        let mut parts = split_message(message.clone());
+        parts.swap(0, 2);
+        let mut assembler = SplitAssembler::new();
+        let (id, data) = put_all(&mut assembler, &parts).unwrap();
+        assert_eq!(ProtocolMessage::decode(id, &data), Ok(message));
+    }
+
+    #[test]
+    fn missing_parts_are_dropped() {
+        let parts = split_message(large_chat_text());
+        let mut assembler = SplitAssembler::new();
+
+        // The last part is missing: nothing is produced.
+        assert_eq!(put_all(&mut assembler, &parts[..2]), None);
+
+        // A new message replaces the incomplete one.
+        let other = ProtocolMessage::ConnectedClientsList { clients: vec![] };
+        assert_eq!(assembler.put(other.id(), 2, 1, vec![]), None);
+        assert_eq!(assembler.put(other.id(), 2, 0, vec![]), Some((24, vec![])));
+
+        // The leftover part of the first message does not complete it.
+        assert_eq!(put_all(&mut assembler, &parts[2..]), None);
+    }
+
+    #[test]
+    fn invalid_part_index_is_ignored() {
+        let mut assembler = SplitAssembler::new();
+        assert_eq!(assembler.put(18, 2, 2, vec![1]), None);
+        assert_eq!(assembler.put(18, 0, 0, vec![1]), None);
+    }
+}
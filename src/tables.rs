@@ -0,0 +1,416 @@
+//! Countries and instruments, identified as in the Jamulus protocol.
+//!
+//! Country IDs are the values of `QLocale::Country` in Qt 5, which Jamulus
+//! sends as is. Instrument IDs follow the instrument table of the reference
+//! client.
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! id_table {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident: $id:ty {
+            $($variant:ident = $value:literal => $display:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum $name {
+            $($variant,)*
+            /// An ID missing from our table, e.g. from a newer client.
+            Unknown($id),
+        }
+        impl $name {
+            /// Looks up an ID as sent over the network.
+            pub fn from_id(id: $id) -> $name {
+                match id {
+                    $($value => $name::$variant,)*
+                    id => $name::Unknown(id),
+                }
+            }
+            /// The ID sent over the network.
+            pub fn id(self) -> $id {
+                match self {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(id) => id,
+                }
+            }
+            /// The English name, as shown by the reference client.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => $display,)*
+                    $name::Unknown(_) => "Unknown",
+                }
+            }
+        }
+    };
+}
+
+id_table! {
+    /// A country, as shown next to a client or server name.
+    pub enum Country: u16 {
+        AnyCountry = 0 => "None",
+        Afghanistan = 1 => "Afghanistan",
+        Albania = 2 => "Albania",
+        Algeria = 3 => "Algeria",
+        AmericanSamoa = 4 => "American Samoa",
+        Andorra = 5 => "Andorra",
+        Angola = 6 => "Angola",
+        Anguilla = 7 => "Anguilla",
+        Antarctica = 8 => "Antarctica",
+        AntiguaAndBarbuda = 9 => "Antigua and Barbuda",
+        Argentina = 10 => "Argentina",
+        Armenia = 11 => "Armenia",
+        Aruba = 12 => "Aruba",
+        Australia = 13 => "Australia",
+        Austria = 14 => "Austria",
+        Azerbaijan = 15 => "Azerbaijan",
+        Bahamas = 16 => "Bahamas",
+        Bahrain = 17 => "Bahrain",
+        Bangladesh = 18 => "Bangladesh",
+        Barbados = 19 => "Barbados",
+        Belarus = 20 => "Belarus",
+        Belgium = 21 => "Belgium",
+        Belize = 22 => "Belize",
+        Benin = 23 => "Benin",
+        Bermuda = 24 => "Bermuda",
+        Bhutan = 25 => "Bhutan",
+        Bolivia = 26 => "Bolivia",
+        BosniaAndHerzegovina = 27 => "Bosnia and Herzegovina",
+        Botswana = 28 => "Botswana",
+        BouvetIsland = 29 => "Bouvet Island",
+        Brazil = 30 => "Brazil",
+        BritishIndianOceanTerritory = 31 => "British Indian Ocean Territory",
+        Brunei = 32 => "Brunei",
+        Bulgaria = 33 => "Bulgaria",
+        BurkinaFaso = 34 => "Burkina Faso",
+        Burundi = 35 => "Burundi",
+        Cambodia = 36 => "Cambodia",
+        Cameroon = 37 => "Cameroon",
+        Canada = 38 => "Canada",
+        CapeVerde = 39 => "Cape Verde",
+        CaymanIslands = 40 => "Cayman Islands",
+        CentralAfricanRepublic = 41 => "Central African Republic",
+        Chad = 42 => "Chad",
+        Chile = 43 => "Chile",
+        China = 44 => "China",
+        ChristmasIsland = 45 => "Christmas Island",
+        CocosIslands = 46 => "Cocos Islands",
+        Colombia = 47 => "Colombia",
+        Comoros = 48 => "Comoros",
+        CongoKinshasa = 49 => "Congo - Kinshasa",
+        CongoBrazzaville = 50 => "Congo - Brazzaville",
+        CookIslands = 51 => "Cook Islands",
+        CostaRica = 52 => "Costa Rica",
+        IvoryCoast = 53 => "Ivory Coast",
+        Croatia = 54 => "Croatia",
+        Cuba = 55 => "Cuba",
+        Cyprus = 56 => "Cyprus",
+        CzechRepublic = 57 => "Czech Republic",
+        Denmark = 58 => "Denmark",
+        Djibouti = 59 => "Djibouti",
+        Dominica = 60 => "Dominica",
+        DominicanRepublic = 61 => "Dominican Republic",
+        EastTimor = 62 => "East Timor",
+        Ecuador = 63 => "Ecuador",
+        Egypt = 64 => "Egypt",
+        ElSalvador = 65 => "El Salvador",
+        EquatorialGuinea = 66 => "Equatorial Guinea",
+        Eritrea = 67 => "Eritrea",
+        Estonia = 68 => "Estonia",
+        Ethiopia = 69 => "Ethiopia",
+        FalklandIslands = 70 => "Falkland Islands",
+        FaroeIslands = 71 => "Faroe Islands",
+        Fiji = 72 => "Fiji",
+        Finland = 73 => "Finland",
+        France = 74 => "France",
+        Guernsey = 75 => "Guernsey",
+        FrenchGuiana = 76 => "French Guiana",
+        FrenchPolynesia = 77 => "French Polynesia",
+        FrenchSouthernTerritories = 78 => "French Southern Territories",
+        Gabon = 79 => "Gabon",
+        Gambia = 80 => "Gambia",
+        Georgia = 81 => "Georgia",
+        Germany = 82 => "Germany",
+        Ghana = 83 => "Ghana",
+        Gibraltar = 84 => "Gibraltar",
+        Greece = 85 => "Greece",
+        Greenland = 86 => "Greenland",
+        Grenada = 87 => "Grenada",
+        Guadeloupe = 88 => "Guadeloupe",
+        Guam = 89 => "Guam",
+        Guatemala = 90 => "Guatemala",
+        Guinea = 91 => "Guinea",
+        GuineaBissau = 92 => "Guinea-Bissau",
+        Guyana = 93 => "Guyana",
+        Haiti = 94 => "Haiti",
+        HeardAndMcDonaldIslands = 95 => "Heard and McDonald Islands",
+        Honduras = 96 => "Honduras",
+        HongKong = 97 => "Hong Kong",
+        Hungary = 98 => "Hungary",
+        Iceland = 99 => "Iceland",
+        India = 100 => "India",
+        Indonesia = 101 => "Indonesia",
+        Iran = 102 => "Iran",
+        Iraq = 103 => "Iraq",
+        Ireland = 104 => "Ireland",
+        Israel = 105 => "Israel",
+        Italy = 106 => "Italy",
+        Jamaica = 107 => "Jamaica",
+        Japan = 108 => "Japan",
+        Jordan = 109 => "Jordan",
+        Kazakhstan = 110 => "Kazakhstan",
+        Kenya = 111 => "Kenya",
+        Kiribati = 112 => "Kiribati",
+        NorthKorea = 113 => "North Korea",
+        SouthKorea = 114 => "South Korea",
+        Kuwait = 115 => "Kuwait",
+        Kyrgyzstan = 116 => "Kyrgyzstan",
+        Laos = 117 => "Laos",
+        Latvia = 118 => "Latvia",
+        Lebanon = 119 => "Lebanon",
+        Lesotho = 120 => "Lesotho",
+        Liberia = 121 => "Liberia",
+        Libya = 122 => "Libya",
+        Liechtenstein = 123 => "Liechtenstein",
+        Lithuania = 124 => "Lithuania",
+        Luxembourg = 125 => "Luxembourg",
+        Macau = 126 => "Macau",
+        Macedonia = 127 => "Macedonia",
+        Madagascar = 128 => "Madagascar",
+        Malawi = 129 => "Malawi",
+        Malaysia = 130 => "Malaysia",
+        Maldives = 131 => "Maldives",
+        Mali = 132 => "Mali",
+        Malta = 133 => "Malta",
+        MarshallIslands = 134 => "Marshall Islands",
+        Martinique = 135 => "Martinique",
+        Mauritania = 136 => "Mauritania",
+        Mauritius = 137 => "Mauritius",
+        Mayotte = 138 => "Mayotte",
+        Mexico = 139 => "Mexico",
+        Micronesia = 140 => "Micronesia",
+        Moldova = 141 => "Moldova",
+        Monaco = 142 => "Monaco",
+        Mongolia = 143 => "Mongolia",
+        Montserrat = 144 => "Montserrat",
+        Morocco = 145 => "Morocco",
+        Mozambique = 146 => "Mozambique",
+        Myanmar = 147 => "Myanmar",
+        Namibia = 148 => "Namibia",
+        Nauru = 149 => "Nauru",
+        Nepal = 150 => "Nepal",
+        Netherlands = 151 => "Netherlands",
+        Curacao = 152 => "Curaçao",
+        NewCaledonia = 153 => "New Caledonia",
+        NewZealand = 154 => "New Zealand",
+        Nicaragua = 155 => "Nicaragua",
+        Niger = 156 => "Niger",
+        Nigeria = 157 => "Nigeria",
+        Niue = 158 => "Niue",
+        NorfolkIsland = 159 => "Norfolk Island",
+        NorthernMarianaIslands = 160 => "Northern Mariana Islands",
+        Norway = 161 => "Norway",
+        Oman = 162 => "Oman",
+        Pakistan = 163 => "Pakistan",
+        Palau = 164 => "Palau",
+        PalestinianTerritories = 165 => "Palestinian Territories",
+        Panama = 166 => "Panama",
+        PapuaNewGuinea = 167 => "Papua New Guinea",
+        Paraguay = 168 => "Paraguay",
+        Peru = 169 => "Peru",
+        Philippines = 170 => "Philippines",
+        Pitcairn = 171 => "Pitcairn",
+        Poland = 172 => "Poland",
+        Portugal = 173 => "Portugal",
+        PuertoRico = 174 => "Puerto Rico",
+        Qatar = 175 => "Qatar",
+        Reunion = 176 => "Réunion",
+        Romania = 177 => "Romania",
+        Russia = 178 => "Russia",
+        Rwanda = 179 => "Rwanda",
+        SaintKittsAndNevis = 180 => "Saint Kitts and Nevis",
+        SaintLucia = 181 => "Saint Lucia",
+        SaintVincentAndTheGrenadines = 182 => "Saint Vincent and the Grenadines",
+        Samoa = 183 => "Samoa",
+        SanMarino = 184 => "San Marino",
+        SaoTomeAndPrincipe = 185 => "São Tomé and Príncipe",
+        SaudiArabia = 186 => "Saudi Arabia",
+        Senegal = 187 => "Senegal",
+        Seychelles = 188 => "Seychelles",
+        SierraLeone = 189 => "Sierra Leone",
+        Singapore = 190 => "Singapore",
+        Slovakia = 191 => "Slovakia",
+        Slovenia = 192 => "Slovenia",
+        SolomonIslands = 193 => "Solomon Islands",
+        Somalia = 194 => "Somalia",
+        SouthAfrica = 195 => "South Africa",
+        SouthGeorgiaAndTheSouthSandwichIslands = 196 => "South Georgia and the South Sandwich Islands",
+        Spain = 197 => "Spain",
+        SriLanka = 198 => "Sri Lanka",
+        SaintHelena = 199 => "Saint Helena",
+        SaintPierreAndMiquelon = 200 => "Saint Pierre and Miquelon",
+        Sudan = 201 => "Sudan",
+        Suriname = 202 => "Suriname",
+        SvalbardAndJanMayenIslands = 203 => "Svalbard and Jan Mayen",
+        Swaziland = 204 => "Swaziland",
+        Sweden = 205 => "Sweden",
+        Switzerland = 206 => "Switzerland",
+        Syria = 207 => "Syria",
+        Taiwan = 208 => "Taiwan",
+        Tajikistan = 209 => "Tajikistan",
+        Tanzania = 210 => "Tanzania",
+        Thailand = 211 => "Thailand",
+        Togo = 212 => "Togo",
+        Tokelau = 213 => "Tokelau",
+        Tonga = 214 => "Tonga",
+        TrinidadAndTobago = 215 => "Trinidad and Tobago",
+        Tunisia = 216 => "Tunisia",
+        Turkey = 217 => "Turkey",
+        Turkmenistan = 218 => "Turkmenistan",
+        TurksAndCaicosIslands = 219 => "Turks and Caicos Islands",
+        Tuvalu = 220 => "Tuvalu",
+        Uganda = 221 => "Uganda",
+        Ukraine = 222 => "Ukraine",
+        UnitedArabEmirates = 223 => "United Arab Emirates",
+        UnitedKingdom = 224 => "United Kingdom",
+        UnitedStates = 225 => "United States",
+        UnitedStatesMinorOutlyingIslands = 226 => "United States Minor Outlying Islands",
+        Uruguay = 227 => "Uruguay",
+        Uzbekistan = 228 => "Uzbekistan",
+        Vanuatu = 229 => "Vanuatu",
+        VaticanCityState = 230 => "Vatican City",
+        Venezuela = 231 => "Venezuela",
+        Vietnam = 232 => "Vietnam",
+        BritishVirginIslands = 233 => "British Virgin Islands",
+        UnitedStatesVirginIslands = 234 => "United States Virgin Islands",
+        WallisAndFutunaIslands = 235 => "Wallis and Futuna",
+        WesternSahara = 236 => "Western Sahara",
+        Yemen = 237 => "Yemen",
+        CanaryIslands = 238 => "Canary Islands",
+        Zambia = 239 => "Zambia",
+        Zimbabwe = 240 => "Zimbabwe",
+        ClippertonIsland = 241 => "Clipperton Island",
+        Montenegro = 242 => "Montenegro",
+        Serbia = 243 => "Serbia",
+        SaintBarthelemy = 244 => "Saint Barthélemy",
+        SaintMartin = 245 => "Saint Martin",
+        LatinAmerica = 246 => "Latin America",
+        AscensionIsland = 247 => "Ascension Island",
+        AlandIslands = 248 => "Åland Islands",
+        DiegoGarcia = 249 => "Diego Garcia",
+        CeutaAndMelilla = 250 => "Ceuta and Melilla",
+        IsleOfMan = 251 => "Isle of Man",
+        Jersey = 252 => "Jersey",
+        TristanDaCunha = 253 => "Tristan da Cunha",
+        SouthSudan = 254 => "South Sudan",
+        Bonaire = 255 => "Bonaire",
+        SintMaarten = 256 => "Sint Maarten",
+        Kosovo = 257 => "Kosovo",
+        EuropeanUnion = 258 => "European Union",
+        OutlyingOceania = 259 => "Outlying Oceania",
+        World = 260 => "World",
+        Europe = 261 => "Europe",
+    }
+}
+
+id_table! {
+    /// The instrument a client plays.
+    pub enum Instrument: u32 {
+        None = 0 => "None",
+        DrumSet = 1 => "Drum Set",
+        Djembe = 2 => "Djembe",
+        ElectricGuitar = 3 => "Electric Guitar",
+        AcousticGuitar = 4 => "Acoustic Guitar",
+        BassGuitar = 5 => "Bass Guitar",
+        Keyboard = 6 => "Keyboard",
+        Synthesizer = 7 => "Synthesizer",
+        GrandPiano = 8 => "Grand Piano",
+        Accordion = 9 => "Accordion",
+        Vocal = 10 => "Vocal",
+        Microphone = 11 => "Microphone",
+        Harmonica = 12 => "Harmonica",
+        Trumpet = 13 => "Trumpet",
+        Trombone = 14 => "Trombone",
+        FrenchHorn = 15 => "French Horn",
+        Tuba = 16 => "Tuba",
+        Saxophone = 17 => "Saxophone",
+        Clarinet = 18 => "Clarinet",
+        Flute = 19 => "Flute",
+        Violin = 20 => "Violin",
+        Cello = 21 => "Cello",
+        DoubleBass = 22 => "Double Bass",
+        Recorder = 23 => "Recorder",
+        Streamer = 24 => "Streamer",
+        Listener = 25 => "Listener",
+        GuitarVocal = 26 => "Guitar+Vocal",
+        KeyboardVocal = 27 => "Keyboard+Vocal",
+        Bodhran = 28 => "Bodhran",
+        Bassoon = 29 => "Bassoon",
+        Oboe = 30 => "Oboe",
+        Harp = 31 => "Harp",
+        Viola = 32 => "Viola",
+        Congas = 33 => "Congas",
+        Bongo = 34 => "Bongo",
+        VocalBass = 35 => "Vocal Bass",
+        VocalTenor = 36 => "Vocal Tenor",
+        VocalAlto = 37 => "Vocal Alto",
+        VocalSoprano = 38 => "Vocal Soprano",
+        Banjo = 39 => "Banjo",
+        Mandolin = 40 => "Mandolin",
+        Ukulele = 41 => "Ukulele",
+        BassUkulele = 42 => "Bass Ukulele",
+        VocalBaritone = 43 => "Vocal Baritone",
+        VocalLead = 44 => "Vocal Lead",
+        MountainDulcimer = 45 => "Mountain Dulcimer",
+        Scratching = 46 => "Scratching",
+        Rapping = 47 => "Rapping",
+        Vibraphone = 48 => "Vibraphone",
+        Conductor = 49 => "Conductor",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_ids() {
+        assert_eq!(Country::from_id(211), Country::Thailand);
+        assert_eq!(Country::Thailand.id(), 211);
+        assert_eq!(Country::UnitedStates.name(), "United States");
+        assert_eq!(Country::from_id(262), Country::Unknown(262));
+        assert_eq!(Country::Unknown(262).id(), 262);
+
+        assert_eq!(Instrument::from_id(25), Instrument::Listener);
+        assert_eq!(Instrument::GuitarVocal.name(), "Guitar+Vocal");
+        assert_eq!(Instrument::from_id(1000), Instrument::Unknown(1000));
+        assert_eq!(Instrument::Unknown(1000).name(), "Unknown");
+    }
+
+    #[test]
+    fn ids_are_contiguous() {
+        for id in 0..=261 {
+            let country = Country::from_id(id);
+            assert_ne!(country, Country::Unknown(id));
+            assert_eq!(country.id(), id);
+        }
+        for id in 0..=49 {
+            let instrument = Instrument::from_id(id);
+            assert_ne!(instrument, Instrument::Unknown(id));
+            assert_eq!(instrument.id(), id);
+        }
+    }
+
+    #[test]
+    fn serializes_as_variant_names() {
+        assert_eq!(
+            serde_json::to_string(&Country::NewZealand).unwrap(),
+            "\"NewZealand\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Instrument>("\"DrumSet\"").unwrap(),
+            Instrument::DrumSet
+        );
+    }
+}